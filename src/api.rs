@@ -0,0 +1,493 @@
+use crate::config::{Config, Priority, ToastDirection, is_valid_channel_name, is_valid_hex_color};
+use crate::markup::{TextFormat, to_html};
+use crate::overlays::{AudioFailure, OverlayInfo};
+use crate::services::{ListenerState, Services};
+use crate::toast_queue::{QueueOutcome, QueueState};
+use crate::websocket::{PROTOCOL_VERSION, ToastMessage, WebSocketMessage};
+use http_body_util::{BodyExt, Full, Limited, combinators::BoxBody};
+use hyper::header::CONTENT_TYPE;
+use hyper::{Method, Request, Response, StatusCode, body::Bytes};
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+
+const MAX_BODY_SIZE: usize = 64 * 1024;
+
+/// Body of `POST /api/toast`. Fields that are left out fall back to the toaster config.
+#[derive(Debug, Deserialize)]
+struct ToastRequest {
+    title: String,
+    content: String,
+    group: Option<String>,
+    color_1: Option<String>,
+    color_2: Option<String>,
+    text_color: Option<String>,
+    duration: Option<u32>,
+    direction: Option<ToastDirection>,
+    play_sound: Option<bool>,
+    channels: Option<Vec<String>>,
+    format: Option<TextFormat>,
+    priority: Option<Priority>,
+}
+
+/// Body of `PATCH /api/toast/{id}`. Only the given fields are changed.
+#[derive(Debug, Deserialize)]
+struct UpdateToastRequest {
+    title: Option<String>,
+    content: Option<String>,
+    #[serde(default)]
+    format: TextFormat,
+}
+
+/// Body of `PUT /api/profile`.
+#[derive(Debug, Deserialize)]
+struct ProfileRequest {
+    name: String,
+}
+
+#[derive(Debug, Serialize)]
+struct StatusResponse {
+    version: &'static str,
+    profile: String,
+    running: bool,
+    clients: usize,
+    overlays: Vec<OverlayInfo>,
+    queue: QueueState,
+    last_toast: Option<ReceiptStatus>,
+    groups: Vec<GroupStatus>,
+}
+
+#[derive(Debug, Serialize)]
+struct ReceiptStatus {
+    id: String,
+    title: String,
+    delivered: usize,
+    shown: usize,
+    dismissed: usize,
+    audio_failures: Vec<AudioFailure>,
+}
+
+#[derive(Debug, Serialize)]
+struct GroupStatus {
+    id: String,
+    name: String,
+    running: bool,
+    next_toast_in_seconds: Option<u64>,
+    next_toast_at: Option<String>,
+}
+
+/// Where the servers actually listen.
+#[derive(Debug, Serialize)]
+struct ServerResponse {
+    http_url: Option<String>,
+    websocket_url: Option<String>,
+    separate_websocket_url: Option<String>,
+    protocol_version: u32,
+    errors: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct ProfilesResponse {
+    active: String,
+    profiles: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+pub async fn handle_api_request(
+    req: Request<hyper::body::Incoming>,
+    services: Services,
+) -> Response<BoxBody<Bytes, Infallible>> {
+    if !services.is_authorized(req.headers(), req.uri().query()) {
+        return error_response(StatusCode::UNAUTHORIZED, "Missing or invalid token");
+    }
+    if !services.is_own_origin(req.headers()) {
+        return error_response(
+            StatusCode::FORBIDDEN,
+            "Requests from other sites are not allowed",
+        );
+    }
+
+    let method = req.method().clone();
+    let path = req.uri().path().to_string();
+    let group_id = query_param(req.uri().query(), "group");
+
+    if let Some(ref id) = group_id
+        && services.config.borrow().find_group(id).is_none()
+    {
+        return error_response(StatusCode::NOT_FOUND, &format!("Unknown group: {}", id));
+    }
+    let group_id = group_id.map(|id| resolve_group_id(&services, &id));
+
+    // The timers and profiles of a broken config.toml are unknown, so leave them alone
+    let controls_timers = (method == Method::POST && path.starts_with("/api/timer/"))
+        || (method == Method::PUT && path == "/api/profile");
+    if controls_timers && let Some(error) = services.config_error.borrow().as_ref() {
+        return error_response(
+            StatusCode::CONFLICT,
+            &format!("The configuration file is invalid: {}", error),
+        );
+    }
+
+    match (method, path.as_str()) {
+        (Method::GET, "/api/status") => get_status(&services).await,
+        (Method::GET, "/api/server") => get_server(&services),
+        (Method::POST, "/api/toast") => match read_json::<ToastRequest>(req).await {
+            Ok(toast) => send_toast(&services, toast).await,
+            Err(response) => response,
+        },
+        (Method::DELETE, "/api/toasts") => broadcast(&services, WebSocketMessage::ClearAll),
+        (Method::DELETE, path) if toast_id(path).is_some() => {
+            let id = toast_id(path).unwrap_or_default().to_string();
+            broadcast(&services, WebSocketMessage::Dismiss { id })
+        }
+        (Method::PATCH, path) if toast_id(path).is_some() => {
+            let id = toast_id(path).unwrap_or_default().to_string();
+            match read_json::<UpdateToastRequest>(req).await {
+                Ok(update) => broadcast(
+                    &services,
+                    WebSocketMessage::UpdateToast {
+                        id,
+                        title_html: update.title.map(|t| to_html(&t, update.format)),
+                        content_html: update.content.map(|c| to_html(&c, update.format)),
+                    },
+                ),
+                Err(response) => response,
+            }
+        }
+        (Method::POST, "/api/timer/start") => {
+            services.timer.start(group_id);
+            json_response(StatusCode::OK, &serde_json::json!({ "ok": true }))
+        }
+        (Method::POST, "/api/timer/stop") => {
+            services.timer.stop(group_id);
+            json_response(StatusCode::OK, &serde_json::json!({ "ok": true }))
+        }
+        (Method::POST, "/api/timer/skip") => {
+            services.timer.skip(group_id);
+            json_response(StatusCode::OK, &serde_json::json!({ "ok": true }))
+        }
+        (Method::GET, "/api/profile") => get_profiles(&services),
+        (Method::PUT, "/api/profile") => match read_json::<ProfileRequest>(req).await {
+            Ok(request) => switch_profile(&services, &request.name),
+            Err(response) => response,
+        },
+        (Method::GET, "/api/config") => json_response(StatusCode::OK, &*services.config.borrow()),
+        (Method::PUT, "/api/config") => match read_json::<Config>(req).await {
+            Ok(config) => put_config(&services, config),
+            Err(response) => response,
+        },
+        (_, "/api/status" | "/api/server" | "/api/toast" | "/api/toasts" | "/api/config")
+        | (_, "/api/profile")
+        | (_, "/api/timer/start" | "/api/timer/stop" | "/api/timer/skip") => {
+            error_response(StatusCode::METHOD_NOT_ALLOWED, "Method not allowed")
+        }
+        (_, path) if toast_id(path).is_some() => {
+            error_response(StatusCode::METHOD_NOT_ALLOWED, "Method not allowed")
+        }
+        _ => error_response(StatusCode::NOT_FOUND, "Unknown API endpoint"),
+    }
+}
+
+async fn get_status(services: &Services) -> Response<BoxBody<Bytes, Infallible>> {
+    let timer_state = services.timer.state();
+    let overlays = services.websocket_server.overlays();
+    let status = StatusResponse {
+        version: std::env!("CARGO_PKG_VERSION"),
+        profile: services.config.borrow().active_profile_name().to_string(),
+        running: timer_state.is_running(),
+        clients: services.websocket_server.get_client_count(),
+        overlays: overlays.overlays(),
+        queue: services.toast_queue.state(),
+        last_toast: overlays.latest_receipt().map(|receipt| ReceiptStatus {
+            delivered: receipt.delivered(),
+            shown: receipt.shown(),
+            dismissed: receipt.dismissed(),
+            id: receipt.toast_id,
+            title: receipt.title,
+            audio_failures: receipt.audio_failures,
+        }),
+        groups: timer_state
+            .groups
+            .iter()
+            .map(|group| GroupStatus {
+                id: group.group_id.clone(),
+                name: group.group_name.clone(),
+                running: group.is_running,
+                next_toast_in_seconds: group.time_until_next_toast().map(|d| d.as_secs()),
+                next_toast_at: group.next_toast_time.map(|t| t.to_rfc3339()),
+            })
+            .collect(),
+    };
+
+    json_response(StatusCode::OK, &status)
+}
+
+fn get_server(services: &Services) -> Response<BoxBody<Bytes, Infallible>> {
+    let status = services.server_status.borrow();
+    let errors = [&status.http, &status.websocket]
+        .into_iter()
+        .filter_map(|state| match state {
+            ListenerState::Failed(error) => Some(error.clone()),
+            _ => None,
+        })
+        .collect();
+
+    json_response(
+        StatusCode::OK,
+        &ServerResponse {
+            http_url: status.http_url(),
+            websocket_url: status.websocket_url(),
+            separate_websocket_url: status.separate_websocket_url(),
+            protocol_version: PROTOCOL_VERSION,
+            errors,
+        },
+    )
+}
+
+async fn send_toast(
+    services: &Services,
+    toast: ToastRequest,
+) -> Response<BoxBody<Bytes, Infallible>> {
+    let mut toaster = {
+        let config = services.config.borrow();
+        let group = match toast.group.as_deref() {
+            Some(id) => config.find_group(id),
+            None => config.groups().first(),
+        };
+
+        match group {
+            Some(group) => group.clone(),
+            None => return error_response(StatusCode::NOT_FOUND, "Unknown group"),
+        }
+    };
+
+    if let Some(color_1) = toast.color_1 {
+        toaster.color_1 = color_1;
+    }
+    if let Some(color_2) = toast.color_2 {
+        toaster.color_2 = color_2;
+    }
+    if let Some(text_color) = toast.text_color {
+        toaster.text_color = text_color;
+    }
+    if let Some(duration) = toast.duration {
+        toaster.duration = duration;
+    }
+    if let Some(direction) = toast.direction {
+        toaster.toast_direction = direction;
+    }
+    if let Some(play_sound) = toast.play_sound {
+        toaster.enable_sound = play_sound;
+    }
+    if let Some(channels) = toast.channels {
+        toaster.channels = channels;
+    }
+    if let Some(format) = toast.format {
+        toaster.text_format = format;
+    }
+    if let Some(priority) = toast.priority {
+        toaster.priority = priority;
+    }
+
+    if let Some(channel) = toaster.channels.iter().find(|c| !is_valid_channel_name(c)) {
+        return error_response(
+            StatusCode::BAD_REQUEST,
+            &format!("Invalid channel name: {}", channel),
+        );
+    }
+
+    for color in [&toaster.color_1, &toaster.color_2, &toaster.text_color] {
+        if !is_valid_hex_color(color) {
+            return error_response(
+                StatusCode::BAD_REQUEST,
+                &format!("Invalid hex color: {}", color),
+            );
+        }
+    }
+
+    if toaster.duration == 0 || toaster.duration > 60 {
+        return error_response(
+            StatusCode::BAD_REQUEST,
+            "Toast duration must be between 1 and 60 seconds",
+        );
+    }
+
+    log::info!(
+        "Sending API toast - Title: {}, Content: {}",
+        toast.title,
+        toast.content
+    );
+
+    let message = ToastMessage::from_config(&toaster, toast.title, toast.content);
+    let id = message.id.clone();
+    let clients = services.websocket_server.get_client_count();
+    if clients == 0 {
+        return error_response(StatusCode::SERVICE_UNAVAILABLE, "No overlay connected");
+    }
+
+    match services.toast_queue.push_with_outcome(message).await {
+        Ok(outcome) => json_response(
+            StatusCode::OK,
+            &serde_json::json!({
+                "ok": outcome != QueueOutcome::Dropped,
+                "id": id,
+                "clients": clients,
+                "queue": outcome,
+            }),
+        ),
+        Err(_) => error_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Toast queue is not running",
+        ),
+    }
+}
+
+fn broadcast(
+    services: &Services,
+    message: WebSocketMessage,
+) -> Response<BoxBody<Bytes, Infallible>> {
+    // The toast queue is always subscribed, so count the connections instead of the receivers
+    let clients = services.websocket_server.get_client_count();
+    if clients == 0 {
+        return error_response(StatusCode::SERVICE_UNAVAILABLE, "No overlay connected");
+    }
+
+    let _ = services.websocket_sender.send(message);
+    json_response(
+        StatusCode::OK,
+        &serde_json::json!({ "ok": true, "clients": clients }),
+    )
+}
+
+/// Extracts `{id}` from `/api/toast/{id}`.
+fn toast_id(path: &str) -> Option<&str> {
+    path.strip_prefix("/api/toast/")
+        .filter(|id| !id.is_empty() && !id.contains('/'))
+}
+
+fn get_profiles(services: &Services) -> Response<BoxBody<Bytes, Infallible>> {
+    let config = services.config.borrow();
+    json_response(
+        StatusCode::OK,
+        &ProfilesResponse {
+            active: config.active_profile_name().to_string(),
+            profiles: config.profiles.iter().map(|p| p.name.clone()).collect(),
+        },
+    )
+}
+
+fn switch_profile(services: &Services, name: &str) -> Response<BoxBody<Bytes, Infallible>> {
+    let config = services.config.borrow().clone();
+    if config.profile(name).is_none() {
+        return error_response(StatusCode::NOT_FOUND, &format!("Unknown profile: {}", name));
+    }
+
+    match services.switch_profile(config, name) {
+        Ok(()) => get_profiles(services),
+        Err(e) => {
+            log::error!("Failed to switch profile through the HTTP API: {}", e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string())
+        }
+    }
+}
+
+fn put_config(services: &Services, config: Config) -> Response<BoxBody<Bytes, Infallible>> {
+    if let Err(e) = config.validate() {
+        return error_response(StatusCode::UNPROCESSABLE_ENTITY, &e.to_string());
+    }
+
+    match services.save_config(config) {
+        Ok(()) => {
+            log::info!("Configuration updated through the HTTP API");
+            json_response(StatusCode::OK, &*services.config.borrow())
+        }
+        Err(e) => {
+            log::error!("Failed to save configuration from the HTTP API: {}", e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string())
+        }
+    }
+}
+
+pub fn query_param(query: Option<&str>, name: &str) -> Option<String> {
+    query?.split('&').find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        if key == name {
+            urlencoding::decode(value).ok().map(|v| v.into_owned())
+        } else {
+            None
+        }
+    })
+}
+
+/// Maps a group name given by the caller to the group id used by the timer.
+fn resolve_group_id(services: &Services, id_or_name: &str) -> String {
+    services
+        .config
+        .borrow()
+        .find_group(id_or_name)
+        .map(|group| group.id.clone())
+        .unwrap_or_else(|| id_or_name.to_string())
+}
+
+async fn read_json<T: serde::de::DeserializeOwned>(
+    req: Request<hyper::body::Incoming>,
+) -> Result<T, Response<BoxBody<Bytes, Infallible>>> {
+    // Browsers only send JSON cross-site after a preflight, which gets no CORS headers
+    let is_json = req
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .is_some_and(|value| value.trim().eq_ignore_ascii_case("application/json"));
+    if !is_json {
+        return Err(error_response(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "Expected Content-Type: application/json",
+        ));
+    }
+
+    let body = match Limited::new(req.into_body(), MAX_BODY_SIZE).collect().await {
+        Ok(collected) => collected.to_bytes(),
+        Err(e) => {
+            return Err(error_response(
+                StatusCode::BAD_REQUEST,
+                &format!("Failed to read request body: {}", e),
+            ));
+        }
+    };
+
+    serde_json::from_slice(&body).map_err(|e| {
+        error_response(
+            StatusCode::BAD_REQUEST,
+            &format!("Invalid JSON body: {}", e),
+        )
+    })
+}
+
+fn json_response<T: Serialize + ?Sized>(
+    status: StatusCode,
+    value: &T,
+) -> Response<BoxBody<Bytes, Infallible>> {
+    let json = serde_json::to_vec(value).unwrap_or_else(|_| b"{}".to_vec());
+    let body = Full::new(Bytes::from(json))
+        .map_err(|never| match never {})
+        .boxed();
+
+    Response::builder()
+        .status(status)
+        .header("content-type", "application/json")
+        .body(body)
+        .unwrap()
+}
+
+fn error_response(status: StatusCode, message: &str) -> Response<BoxBody<Bytes, Infallible>> {
+    json_response(
+        status,
+        &ErrorResponse {
+            error: message.to_string(),
+        },
+    )
+}
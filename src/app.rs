@@ -1,27 +1,40 @@
-use crate::audio_manager::AudioManager;
-use crate::config::{Config, ContentSwitchMode, ToastDirection};
-use crate::http_server::HttpServer;
-use crate::timer::TimerService;
-use crate::websocket::{WebSocketMessage, WebSocketServer};
+use crate::audio_manager::{AUDIO_EXTENSIONS, AudioManager};
+use crate::auth::{TOKEN_PARAM, generate_token};
+use crate::bundle;
+use crate::config::{
+    Config, ConfigLoadError, ContentSwitchMode, DEFAULT_WEBSOCKET_PORT, MAX_VISIBLE_TOASTS,
+    MissedToastPolicy, OverlapPolicy, Priority, Profile, Reminder, ScheduleMode, ToastDirection,
+    ToasterConfig, Weekday, is_valid_channel_name,
+};
+use crate::markup::TextFormat;
+use crate::preview::{render_formatted, render_toast_preview};
+use crate::services::{ListenerState, Services, is_loopback_host};
+use crate::template::{self, TemplateContext};
+use crate::timer::GroupTimerState;
+use crate::validation::{ConfigIssue, IssueKind, Severity, Validation};
+use crate::websocket::ToastMessage;
 use eframe::egui;
-use open;
-use std::sync::Arc;
-use tokio::sync::broadcast;
+use tokio::sync::watch;
 
 pub struct ObsReminderApp {
     config: Config,
-    config_path: String,
+    saved_config: Config, // as last saved or loaded, to tell whether there are unsaved edits
+    external_config: Option<Config>, // outside change waiting while there are unsaved edits
+    load_error: Option<ConfigLoadError>, // config.toml was unusable at startup
+    validation: Validation, // issues of the edited configuration
 
     // UI state
+    selected_group: usize,
     new_title: String,
     new_content: String,
+    new_channel: String,
+    bundle_message: Option<Result<String, String>>, // outcome of the last export or import
 
     // Services
-    websocket_sender: Option<broadcast::Sender<WebSocketMessage>>,
-    timer_service: Option<TimerService>,
+    services: Services,
+    config_updates: watch::Receiver<Config>,
 
     // Status
-    is_running: bool,
     connection_status: String,
 
     // Test toast cooldown
@@ -29,36 +42,20 @@ pub struct ObsReminderApp {
 }
 
 impl ObsReminderApp {
-    pub fn new(config: Config, config_path: String) -> Self {
-        // Initialize WebSocket server immediately on startup
-        let websocket_server = Arc::new(WebSocketServer::new());
-        let websocket_sender = websocket_server.get_sender();
-
-        // Start WebSocket server in background
-        let server_clone = websocket_server.clone();
-        tokio::spawn(async move {
-            if let Err(e) = server_clone.start().await {
-                log::error!("WebSocket server error: {}", e);
-            }
-        });
-
-        // Initialize and start HTTP server
-        let http_server = HttpServer::new(8080);
-        let http_server_clone = http_server.clone();
-        tokio::spawn(async move {
-            if let Err(e) = http_server_clone.start().await {
-                log::error!("HTTP server error: {}", e);
-            }
-        });
-
+    pub fn new(config: Config, services: Services, load_error: Option<ConfigLoadError>) -> Self {
         Self {
-            timer_service: Some(TimerService::new(&config)),
-            websocket_sender: Some(websocket_sender),
+            config_updates: services.config.subscribe(),
+            services,
+            saved_config: config.clone(),
+            external_config: None,
+            load_error,
+            validation: Validation::default(),
             config,
-            config_path,
+            selected_group: 0,
             new_title: String::new(),
             new_content: String::new(),
-            is_running: false,
+            new_channel: String::new(),
+            bundle_message: None,
             connection_status: "Servers Ready".to_string(),
             test_toast_cooldown: None,
         }
@@ -87,65 +84,195 @@ impl ObsReminderApp {
 
     fn render_header(&mut self, ui: &mut egui::Ui) {
         ui.heading("OBS Reminder");
+
+        if let Some(error) = self.services.config_error.borrow().as_ref() {
+            ui.colored_label(
+                egui::Color32::from_rgb(200, 0, 0),
+                format!("⚠ config.toml 有错误，继续使用之前的配置：{}", error),
+            );
+        }
+
+        self.render_profiles(ui);
         ui.separator();
     }
 
-    fn render_settings(&mut self, ui: &mut egui::Ui) {
-        ui.heading("设置");
+    fn render_profiles(&mut self, ui: &mut egui::Ui) {
+        let active = self.config.active_profile_name().to_string();
+        let mut switch_to = None;
+        let mut removed = None;
 
-        // Content
-        // Toaster titles section
-        ui.label("通知标题: (允许多行)");
+        ui.horizontal(|ui| {
+            ui.label("方案:");
+            egui::ComboBox::from_id_salt("profile")
+                .selected_text(&active)
+                .show_ui(ui, |ui| {
+                    for profile in &self.config.profiles {
+                        if ui
+                            .selectable_label(profile.name == active, &profile.name)
+                            .clicked()
+                            && profile.name != active
+                        {
+                            switch_to = Some(profile.name.clone());
+                        }
+                    }
+                });
 
-        // Display existing titles
-        let mut titles_to_remove = Vec::new();
-        for (i, title) in self.config.toaster.titles.iter_mut().enumerate() {
-            ui.horizontal(|ui| {
-                ui.text_edit_singleline(title);
-                if ui.button("移除").clicked() {
-                    titles_to_remove.push(i);
+            if ui.button("复制方案").clicked() {
+                let mut name = format!("{} 副本", active);
+                for i in 2.. {
+                    if self.config.profile(&name).is_none() {
+                        break;
+                    }
+                    name = format!("{} 副本{}", active, i);
                 }
-            });
-        }
 
-        // Remove titles marked for deletion (in reverse order to maintain indices)
-        for &i in titles_to_remove.iter().rev() {
-            self.config.toaster.titles.remove(i);
-        }
+                let index = self.config.active_profile_index();
+                let mut profile = self.config.profiles[index].duplicate(&name);
+                copy_sound_files(&mut profile);
+                self.config.profiles.push(profile);
+                switch_to = Some(name);
+            }
+
+            if self.config.profiles.len() > 1 && ui.button("删除方案").clicked() {
+                let index = self.config.active_profile_index();
+                removed = Some(self.config.profiles.remove(index));
+                switch_to = Some(self.config.profiles[0].name.clone());
+            }
+        });
 
-        // Add new title
         ui.horizontal(|ui| {
-            ui.text_edit_singleline(&mut self.new_title);
-            if ui.button("添加标题").clicked() && !self.new_title.is_empty() {
-                self.config.toaster.titles.push(self.new_title.clone());
-                self.new_title.clear();
+            ui.label("方案名称:");
+            let index = self.config.active_profile_index();
+            if let Some(profile) = self.config.profiles.get_mut(index)
+                && ui.text_edit_singleline(&mut profile.name).changed()
+            {
+                self.config.active_profile = profile.name.clone();
             }
         });
+        let index = self.config.active_profile_index();
+        render_issues(
+            ui,
+            self.validation.field(&format!("profiles[{}].name", index)),
+        );
+        ui.small("切换方案会保存当前设置；正在运行的计时器会改用新方案的分组");
 
-        // Toaster contents section
-        ui.label("通知内容: (允许多行)");
+        ui.horizontal(|ui| {
+            if ui.button("导出方案…").clicked() {
+                self.export_profile(&active);
+            }
+            if ui.button("导入方案…").clicked() {
+                self.import_bundle();
+            }
+        });
+        match &self.bundle_message {
+            Some(Ok(message)) => {
+                ui.colored_label(egui::Color32::from_rgb(0, 150, 0), message);
+            }
+            Some(Err(message)) => {
+                ui.colored_label(egui::Color32::from_rgb(150, 0, 0), message);
+            }
+            None => {}
+        }
 
-        // Display existing contents
-        let mut contents_to_remove = Vec::new();
-        for (i, content) in self.config.toaster.contents.iter_mut().enumerate() {
-            ui.horizontal(|ui| {
-                ui.text_edit_singleline(content);
-                if ui.button("移除").clicked() {
-                    contents_to_remove.push(i);
+        if let Some(name) = switch_to
+            && self.switch_profile(&name)
+            && let Some(removed) = removed
+            && let Ok(audio_manager) = AudioManager::new()
+        {
+            for file_id in removed
+                .groups
+                .iter()
+                .filter_map(|g| g.sound_file_id.as_ref())
+            {
+                let _ = audio_manager.remove_audio_file(file_id);
+            }
+        }
+    }
+
+    fn render_settings(&mut self, ui: &mut egui::Ui) {
+        ui.heading("设置");
+
+        // Issues are looked up by their path in config.toml
+        let path = format!(
+            "profiles[{}].groups[{}]",
+            self.config.active_profile_index(),
+            self.selected_group
+        );
+        let field = |name: &str| format!("{}.{}", path, name);
+        let validation = &self.validation;
+
+        let Some(group) = self.config.groups_mut().get_mut(self.selected_group) else {
+            return;
+        };
+        render_issues(ui, validation.field(&field("id")));
+
+        ui.horizontal(|ui| {
+            ui.label("分组名称:");
+            ui.text_edit_singleline(&mut group.name);
+        });
+
+        // Content
+        // Each reminder pairs a title with the content shown under it
+        ui.label("提醒内容: (标题与内容成对显示)");
+        ui.small("可用变量: {time} {date} {stream_uptime} {count} {next_in} {random:a|b|c}，用 {{ }} 输入花括号");
+        render_issues(ui, validation.field(&field("reminders")));
+
+        let text_format = group.text_format;
+        let mut reminders_to_remove = Vec::new();
+        for (i, reminder) in group.reminders.iter_mut().enumerate() {
+            ui.group(|ui| {
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut reminder.enabled, "");
+                    ui.label("标题:");
+                    ui.text_edit_singleline(&mut reminder.title);
+                });
+                ui.horizontal(|ui| {
+                    ui.label("内容:");
+                    ui.text_edit_singleline(&mut reminder.content);
+                });
+                if text_format != TextFormat::Plain {
+                    ui.horizontal_wrapped(|ui| {
+                        ui.label("预览:");
+                        render_formatted(ui, &reminder.title, text_format);
+                        ui.label("|");
+                        render_formatted(ui, &reminder.content, text_format);
+                    });
                 }
+                ui.horizontal(|ui| {
+                    ui.label("权重:");
+                    ui.add(egui::DragValue::new(&mut reminder.weight).range(1..=1000));
+                    if ui.button("移除").clicked() {
+                        reminders_to_remove.push(i);
+                    }
+                });
+                render_issues(ui, validation.within(&field(&format!("reminders[{}]", i))));
             });
         }
 
-        // Remove contents marked for deletion
-        for &i in contents_to_remove.iter().rev() {
-            self.config.toaster.contents.remove(i);
+        // Remove reminders marked for deletion (in reverse order to maintain indices)
+        for &i in reminders_to_remove.iter().rev() {
+            group.reminders.remove(i);
         }
 
-        // Add new content
-        ui.horizontal(|ui| {
-            ui.text_edit_singleline(&mut self.new_content);
-            if ui.button("添加内容").clicked() && !self.new_content.is_empty() {
-                self.config.toaster.contents.push(self.new_content.clone());
+        // Add new reminder
+        ui.group(|ui| {
+            ui.horizontal(|ui| {
+                ui.label("标题:");
+                ui.text_edit_singleline(&mut self.new_title);
+            });
+            ui.horizontal(|ui| {
+                ui.label("内容:");
+                ui.text_edit_singleline(&mut self.new_content);
+            });
+            let can_add = !self.new_title.is_empty() || !self.new_content.is_empty();
+            if ui
+                .add_enabled(can_add, egui::Button::new("添加提醒"))
+                .clicked()
+            {
+                group
+                    .reminders
+                    .push(Reminder::new(&self.new_title, &self.new_content));
+                self.new_title.clear();
                 self.new_content.clear();
             }
         });
@@ -153,37 +280,127 @@ impl ObsReminderApp {
         ui.horizontal(|ui| {
             ui.label("内容切换模式:");
             egui::ComboBox::from_id_salt("switch_mode")
-                .selected_text(match self.config.toaster.content_switch_mode {
-                    ContentSwitchMode::Random => "随机",
-                    ContentSwitchMode::Sequential => "顺序",
-                })
+                .selected_text(switch_mode_label(&group.content_switch_mode))
                 .show_ui(ui, |ui| {
-                    ui.selectable_value(
-                        &mut self.config.toaster.content_switch_mode,
+                    for mode in [
                         ContentSwitchMode::Random,
-                        "随机",
-                    );
-                    ui.selectable_value(
-                        &mut self.config.toaster.content_switch_mode,
                         ContentSwitchMode::Sequential,
-                        "顺序",
-                    );
+                        ContentSwitchMode::Shuffle,
+                        ContentSwitchMode::Weighted,
+                        ContentSwitchMode::LeastRecent,
+                    ] {
+                        let label = switch_mode_label(&mode);
+                        ui.selectable_value(&mut group.content_switch_mode, mode, label);
+                    }
                 });
         });
 
         ui.separator();
 
-        // Interval time
+        // Schedule
+        let schedule = &mut group.schedule;
         ui.horizontal(|ui| {
-            ui.label("间隔时间: (分钟)");
-            ui.add(egui::DragValue::new(&mut self.config.toaster.interval_time).range(1..=1440));
+            ui.label("定时模式:");
+            egui::ComboBox::from_id_salt("schedule_mode")
+                .selected_text(schedule_mode_label(schedule.mode))
+                .show_ui(ui, |ui| {
+                    for mode in [
+                        ScheduleMode::Interval,
+                        ScheduleMode::Hourly,
+                        ScheduleMode::Daily,
+                    ] {
+                        ui.selectable_value(&mut schedule.mode, mode, schedule_mode_label(mode));
+                    }
+                });
         });
 
+        match schedule.mode {
+            ScheduleMode::Interval => {
+                // Interval time
+                ui.horizontal(|ui| {
+                    ui.label("间隔时间: (分钟)");
+                    ui.add(egui::DragValue::new(&mut group.interval_time).range(1..=1440));
+                });
+                render_issues(ui, validation.field(&field("interval_time")));
+            }
+            ScheduleMode::Hourly => {
+                ui.label("每小时的第几分钟:");
+                let mut minutes_to_remove = Vec::new();
+                ui.horizontal_wrapped(|ui| {
+                    for (i, minute) in schedule.minutes.iter_mut().enumerate() {
+                        ui.add(egui::DragValue::new(minute).range(0..=59).prefix(":"));
+                        if ui.small_button("×").clicked() {
+                            minutes_to_remove.push(i);
+                        }
+                    }
+                    if ui.button("添加").clicked() {
+                        schedule.minutes.push(0);
+                    }
+                });
+                for &i in minutes_to_remove.iter().rev() {
+                    schedule.minutes.remove(i);
+                }
+            }
+            ScheduleMode::Daily => {
+                ui.label("每天的时间: (HH:MM)");
+                let mut times_to_remove = Vec::new();
+                for (i, time) in schedule.times.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.add(egui::TextEdit::singleline(time).desired_width(60.0));
+                        if ui.button("移除").clicked() {
+                            times_to_remove.push(i);
+                        }
+                    });
+                }
+                for &i in times_to_remove.iter().rev() {
+                    schedule.times.remove(i);
+                }
+                if ui.button("添加时间").clicked() {
+                    schedule.times.push("21:00".to_string());
+                }
+            }
+        }
+
+        ui.horizontal_wrapped(|ui| {
+            ui.label("星期:");
+            for weekday in Weekday::ALL {
+                let mut selected = schedule.weekdays.contains(&weekday);
+                if ui.checkbox(&mut selected, weekday_label(weekday)).changed() {
+                    if selected {
+                        schedule.weekdays.push(weekday);
+                    } else {
+                        schedule.weekdays.retain(|w| *w != weekday);
+                    }
+                }
+            }
+        });
+
+        ui.horizontal(|ui| {
+            let mut use_window = schedule.window_start.is_some();
+            if ui.checkbox(&mut use_window, "时间段:").changed() {
+                if use_window {
+                    schedule.window_start = Some("20:00".to_string());
+                    schedule.window_end = Some("23:30".to_string());
+                } else {
+                    schedule.window_start = None;
+                    schedule.window_end = None;
+                }
+            }
+            if let (Some(start), Some(end)) = (&mut schedule.window_start, &mut schedule.window_end)
+            {
+                ui.add(egui::TextEdit::singleline(start).desired_width(50.0));
+                ui.label("-");
+                ui.add(egui::TextEdit::singleline(end).desired_width(50.0));
+            }
+        });
+        render_issues(ui, validation.within(&field("schedule")));
+
         // Duration
         ui.horizontal(|ui| {
             ui.label("持续时间: (秒)");
-            ui.add(egui::DragValue::new(&mut self.config.toaster.duration).range(1..=60));
+            ui.add(egui::DragValue::new(&mut group.duration).range(1..=60));
         });
+        render_issues(ui, validation.field(&field("duration")));
 
         ui.separator();
 
@@ -192,87 +409,164 @@ impl ObsReminderApp {
             ui.label("通知颜色 #1:");
 
             // Convert hex string to Color32
-            let mut color1 = hex_to_color32(&self.config.toaster.color_1);
+            let mut color1 = hex_to_color32(&group.color_1);
 
             if ui.color_edit_button_srgba(&mut color1).changed() {
-                self.config.toaster.color_1 = color32_to_hex(color1);
+                group.color_1 = color32_to_hex(color1);
             }
 
-            ui.label(&self.config.toaster.color_1);
+            ui.label(&group.color_1);
         });
+        render_issues(ui, validation.field(&field("color_1")));
 
         ui.horizontal(|ui| {
             ui.label("通知颜色 #2:");
 
             // Convert hex string to Color32
-            let mut color2 = hex_to_color32(&self.config.toaster.color_2);
+            let mut color2 = hex_to_color32(&group.color_2);
 
             if ui.color_edit_button_srgba(&mut color2).changed() {
-                self.config.toaster.color_2 = color32_to_hex(color2);
+                group.color_2 = color32_to_hex(color2);
             }
 
-            ui.label(&self.config.toaster.color_2);
+            ui.label(&group.color_2);
         });
+        render_issues(ui, validation.field(&field("color_2")));
 
         ui.horizontal(|ui| {
             ui.label("文字颜色:");
 
             // Convert hex string to Color32
-            let mut text_color = hex_to_color32(&self.config.toaster.text_color);
+            let mut text_color = hex_to_color32(&group.text_color);
 
             if ui.color_edit_button_srgba(&mut text_color).changed() {
-                self.config.toaster.text_color = color32_to_hex(text_color);
+                group.text_color = color32_to_hex(text_color);
             }
 
-            ui.label(&self.config.toaster.text_color);
+            ui.label(&group.text_color);
         });
+        render_issues(ui, validation.field(&field("text_color")));
 
         ui.horizontal(|ui| {
             ui.label("进入方向:");
             egui::ComboBox::from_id_salt("toast_direction")
-                .selected_text(match self.config.toaster.toast_direction {
+                .selected_text(match group.toast_direction {
                     ToastDirection::Top => "从上方",
                     ToastDirection::Bottom => "从下方",
                     ToastDirection::Left => "从左侧",
                     ToastDirection::Right => "从右侧",
                 })
                 .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut group.toast_direction, ToastDirection::Top, "从上方");
                     ui.selectable_value(
-                        &mut self.config.toaster.toast_direction,
-                        ToastDirection::Top,
-                        "从上方",
-                    );
-                    ui.selectable_value(
-                        &mut self.config.toaster.toast_direction,
+                        &mut group.toast_direction,
                         ToastDirection::Bottom,
                         "从下方",
                     );
+                    ui.selectable_value(&mut group.toast_direction, ToastDirection::Left, "从左侧");
                     ui.selectable_value(
-                        &mut self.config.toaster.toast_direction,
-                        ToastDirection::Left,
-                        "从左侧",
-                    );
-                    ui.selectable_value(
-                        &mut self.config.toaster.toast_direction,
+                        &mut group.toast_direction,
                         ToastDirection::Right,
                         "从右侧",
                     );
                 });
         });
 
+        ui.horizontal(|ui| {
+            ui.label("优先级:");
+            egui::ComboBox::from_id_salt("priority")
+                .selected_text(priority_label(group.priority))
+                .show_ui(ui, |ui| {
+                    for priority in [Priority::Low, Priority::Normal, Priority::High] {
+                        ui.selectable_value(
+                            &mut group.priority,
+                            priority,
+                            priority_label(priority),
+                        );
+                    }
+                });
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("文本格式:");
+            egui::ComboBox::from_id_salt("text_format")
+                .selected_text(text_format_label(group.text_format))
+                .show_ui(ui, |ui| {
+                    for format in [TextFormat::Plain, TextFormat::Markup, TextFormat::Markdown] {
+                        ui.selectable_value(
+                            &mut group.text_format,
+                            format,
+                            text_format_label(format),
+                        );
+                    }
+                });
+        });
+        match group.text_format {
+            TextFormat::Plain => {}
+            TextFormat::Markup => {
+                ui.small("支持 <b>粗体</b> <i>斜体</i> <u>下划线</u> <s>删除线</s> <br> 与 <color=#FF0000>颜色</color>");
+            }
+            TextFormat::Markdown => {
+                ui.small("支持 **粗体** *斜体* ~~删除线~~ `代码` [链接](https://…) 与 <br> 换行");
+            }
+        }
+
+        // Overlay channels
+        ui.horizontal_wrapped(|ui| {
+            ui.label("浏览器源频道:");
+            if group.channels.is_empty() {
+                ui.colored_label(egui::Color32::GRAY, "全部");
+            }
+
+            let mut channel_to_remove = None;
+            for (i, channel) in group.channels.iter().enumerate() {
+                if ui.small_button(format!("{} ✖", channel)).clicked() {
+                    channel_to_remove = Some(i);
+                }
+            }
+            if let Some(i) = channel_to_remove {
+                group.channels.remove(i);
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut self.new_channel)
+                    .hint_text("例如 bottom-left")
+                    .desired_width(120.0),
+            );
+            let channel = self.new_channel.trim();
+            let can_add =
+                is_valid_channel_name(channel) && !group.channels.iter().any(|c| c == channel);
+            if ui
+                .add_enabled(can_add, egui::Button::new("添加频道"))
+                .clicked()
+            {
+                group.channels.push(channel.to_string());
+                self.new_channel.clear();
+            }
+        });
+        render_issues(ui, validation.within(&field("channels")));
+        ui.small("留空则显示在所有浏览器源；在浏览器源地址后加 ?channel=频道名 即可只接收该频道");
+
+        // Live preview of the first enabled reminder with the settings above
+        egui::CollapsingHeader::new("提示预览")
+            .default_open(true)
+            .show(ui, |ui| render_toast_preview(ui, group));
+
         ui.separator();
 
         // Sound settings
         ui.heading("音效设置");
 
         ui.horizontal(|ui| {
-            ui.checkbox(&mut self.config.toaster.enable_sound, "启用音效");
+            ui.checkbox(&mut group.enable_sound, "启用音效");
         });
+        render_issues(ui, validation.field(&field("sound_file_id")));
 
-        if self.config.toaster.enable_sound {
+        if group.enable_sound {
             ui.horizontal(|ui| {
                 ui.label("音效文件:");
-                if let Some(ref name) = self.config.toaster.sound_file_name {
+                if let Some(ref name) = group.sound_file_name {
                     ui.label(name);
                 } else {
                     ui.label("没有文件");
@@ -280,7 +574,7 @@ impl ObsReminderApp {
 
                 if ui.button("浏览...").clicked()
                     && let Some(path) = rfd::FileDialog::new()
-                        .add_filter("音频文件", &["mp3", "wav", "ogg", "m4a"])
+                        .add_filter("音频文件", &AUDIO_EXTENSIONS)
                         .pick_file()
                 {
                     // Get the filename for display
@@ -296,12 +590,12 @@ impl ObsReminderApp {
                             match audio_manager.add_audio_file(&path) {
                                 Ok(file_id) => {
                                     // Remove old file if exists
-                                    if let Some(old_id) = &self.config.toaster.sound_file_id {
+                                    if let Some(old_id) = &group.sound_file_id {
                                         let _ = audio_manager.remove_audio_file(old_id);
                                     }
 
-                                    self.config.toaster.sound_file_id = Some(file_id);
-                                    self.config.toaster.sound_file_name = Some(filename);
+                                    group.sound_file_id = Some(file_id);
+                                    group.sound_file_name = Some(filename);
                                     log::info!("Audio file added successfully");
                                 }
                                 Err(e) => {
@@ -315,43 +609,180 @@ impl ObsReminderApp {
                     }
                 }
 
-                if self.config.toaster.sound_file_id.is_some() && ui.button("Clear").clicked() {
+                if group.sound_file_id.is_some() && ui.button("Clear").clicked() {
                     // Remove the cached file
-                    if let Some(file_id) = &self.config.toaster.sound_file_id
+                    if let Some(file_id) = &group.sound_file_id
                         && let Ok(audio_manager) = AudioManager::new()
                     {
                         let _ = audio_manager.remove_audio_file(file_id);
                     }
 
-                    self.config.toaster.sound_file_id = None;
-                    self.config.toaster.sound_file_name = None;
+                    group.sound_file_id = None;
+                    group.sound_file_name = None;
                 }
             });
         }
+
+        ui.separator();
+
+        // Application settings
+        ui.heading("应用设置");
+
+        ui.horizontal(|ui| {
+            ui.label("关闭期间错过的提醒:");
+            let policy = &mut self.config.app.missed_toast_policy;
+            egui::ComboBox::from_id_salt("missed_toast_policy")
+                .selected_text(missed_policy_label(*policy))
+                .show_ui(ui, |ui| {
+                    for option in [MissedToastPolicy::Skip, MissedToastPolicy::FireOnce] {
+                        ui.selectable_value(policy, option, missed_policy_label(option));
+                    }
+                });
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("提示重叠时:");
+            let app = &mut self.config.app;
+            egui::ComboBox::from_id_salt("overlap_policy")
+                .selected_text(overlap_policy_label(app.overlap_policy))
+                .show_ui(ui, |ui| {
+                    for option in [
+                        OverlapPolicy::Stack,
+                        OverlapPolicy::Queue,
+                        OverlapPolicy::Replace,
+                        OverlapPolicy::Drop,
+                    ] {
+                        let label = overlap_policy_label(option);
+                        ui.selectable_value(&mut app.overlap_policy, option, label);
+                    }
+                });
+            if app.overlap_policy == OverlapPolicy::Stack {
+                ui.label("最多同时显示:");
+                ui.add(
+                    egui::DragValue::new(&mut app.max_visible_toasts).range(1..=MAX_VISIBLE_TOASTS),
+                );
+            }
+        });
+        render_issues(ui, self.validation.field("app.max_visible_toasts"));
+        ui.small("优先级高的提示先显示，并可替换正在显示的低优先级提示");
+
+        // Server addresses only take effect after a restart
+        let server = &mut self.config.server;
+        ui.horizontal(|ui| {
+            ui.label("监听地址:");
+            ui.add(egui::TextEdit::singleline(&mut server.host).desired_width(120.0));
+        });
+        render_issues(ui, self.validation.field("server.host"));
+        ui.horizontal(|ui| {
+            ui.label("HTTP 端口:");
+            ui.add(egui::DragValue::new(&mut server.http_port).range(1..=65535));
+        });
+        ui.horizontal(|ui| {
+            let mut separate = server.websocket_port.is_some();
+            if ui
+                .checkbox(&mut separate, "单独的 WebSocket 端口")
+                .changed()
+            {
+                server.websocket_port = separate.then_some(DEFAULT_WEBSOCKET_PORT);
+            }
+            if let Some(port) = &mut server.websocket_port {
+                ui.add(egui::DragValue::new(port).range(1..=65535));
+            }
+        });
+        render_issues(ui, self.validation.field("server.websocket_port"));
+        ui.checkbox(&mut server.port_fallback, "端口被占用时自动使用其他端口");
+        ui.horizontal(|ui| {
+            ui.label("访问令牌:");
+            let mut token = server.token.clone().unwrap_or_default();
+            if ui
+                .add(
+                    egui::TextEdit::singleline(&mut token)
+                        .password(true)
+                        .hint_text("留空则不验证")
+                        .desired_width(160.0),
+                )
+                .changed()
+            {
+                server.token = Some(token).filter(|t| !t.is_empty());
+            }
+            if ui.button("生成").clicked() {
+                server.token = Some(generate_token());
+            }
+        });
+        if server.token.is_none() && !is_loopback_host(&server.host) {
+            ui.colored_label(
+                egui::Color32::from_rgb(200, 150, 0),
+                "⚠ 未设置访问令牌时，局域网内任何人都可以发送提示和读取音频文件",
+            );
+        }
+        ui.small("0.0.0.0 允许局域网内其他设备访问；浏览器源始终通过 HTTP 端口的 /ws 连接；修改后需重启生效");
+    }
+
+    fn render_groups(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal_wrapped(|ui| {
+            ui.label("提醒分组:");
+            for (i, group) in self.config.groups().iter().enumerate() {
+                if ui
+                    .selectable_label(self.selected_group == i, &group.name)
+                    .clicked()
+                {
+                    self.selected_group = i;
+                }
+            }
+
+            if ui.button("添加分组").clicked() {
+                let name = format!("分组{}", self.config.groups().len() + 1);
+                self.config.groups_mut().push(ToasterConfig {
+                    name,
+                    ..Default::default()
+                });
+                self.selected_group = self.config.groups().len() - 1;
+            }
+
+            if self.config.groups().len() > 1 && ui.button("删除分组").clicked() {
+                let removed = self.config.groups_mut().remove(self.selected_group);
+                if let Some(file_id) = &removed.sound_file_id
+                    && let Ok(audio_manager) = AudioManager::new()
+                {
+                    let _ = audio_manager.remove_audio_file(file_id);
+                }
+                self.selected_group = self.selected_group.min(self.config.groups().len() - 1);
+            }
+        });
+
+        ui.separator();
     }
 
     fn render_controls(&mut self, ui: &mut egui::Ui) {
+        let timer_state = self.services.timer.state();
+        let is_running = timer_state.is_running();
+        let all_running = timer_state.groups.iter().all(|group| group.is_running);
+        let has_errors = self.validation.has_errors();
+
         ui.separator();
 
         ui.horizontal(|ui| {
-            let start_button = egui::Button::new("开始").fill(if self.is_running {
+            let start_button = egui::Button::new("全部开始").fill(if all_running {
                 egui::Color32::GRAY
             } else {
                 egui::Color32::from_rgb(0, 150, 0)
             });
 
-            if ui.add_enabled(!self.is_running, start_button).clicked() {
-                self.start_service();
+            if ui
+                .add_enabled(!all_running && !has_errors, start_button)
+                .clicked()
+            {
+                self.start_service(None);
             }
 
-            let stop_button = egui::Button::new("停止").fill(if !self.is_running {
+            let stop_button = egui::Button::new("全部停止").fill(if !is_running {
                 egui::Color32::GRAY
             } else {
                 egui::Color32::from_rgb(150, 0, 0)
             });
 
-            if ui.add_enabled(self.is_running, stop_button).clicked() {
-                self.stop_service();
+            if ui.add_enabled(is_running, stop_button).clicked() {
+                self.stop_service(None);
             }
 
             // Test Toast button with cooldown
@@ -370,120 +801,232 @@ impl ObsReminderApp {
                 self.send_test_toast();
             }
 
-            if ui.button("保存").clicked() {
+            if ui
+                .add_enabled(!has_errors, egui::Button::new("保存"))
+                .clicked()
+            {
                 self.save_configuration();
             }
         });
 
+        // Starting or saving would only fail, so say why instead
+        if has_errors {
+            ui.colored_label(
+                egui::Color32::from_rgb(200, 0, 0),
+                "设置中有错误，修正后才能开始或保存：",
+            );
+            for issue in self.validation.errors() {
+                ui.colored_label(
+                    egui::Color32::from_rgb(200, 0, 0),
+                    format!("• {}: {}", issue.path, issue_label(&issue.kind)),
+                );
+            }
+        }
+
         ui.separator();
 
         // Status display
         ui.vertical(|ui| {
             ui.label("信息:");
-            let status_color = if self.is_running {
+            let status_color = if is_running {
                 egui::Color32::from_rgb(0, 150, 0)
             } else {
                 egui::Color32::from_rgb(150, 0, 0)
             };
-            ui.colored_label(
-                status_color,
-                if self.is_running {
-                    "运行中"
-                } else {
-                    "已停止"
-                },
-            );
+            ui.colored_label(status_color, if is_running { "运行中" } else { "已停止" });
+
+            let server_status = self.services.server_status.borrow().clone();
 
             ui.label("Browser: ");
-            ui.horizontal(|ui| {
-                ui.colored_label(egui::Color32::from_rgb(0, 150, 0), "localhost:8080");
-                if ui.button("复制").clicked() {
-                    ui.ctx().copy_text("http://localhost:8080".to_string());
-                }
-                if ui.button("打开").clicked() {
-                    tokio::spawn(async {
-                        if let Err(e) = open::that("http://localhost:8080") {
-                            log::error!("Failed to open browser: {}", e);
+            ui.horizontal(|ui| match (&server_status.http, server_status.http_url()) {
+                (_, Some(url)) => {
+                    // The browser source needs the token to connect, so it is part of the URL
+                    let url = match &self.services.config.borrow().server.token {
+                        Some(token) if !token.is_empty() => {
+                            format!("{}/?{}={}", url, TOKEN_PARAM, urlencoding::encode(token))
                         }
-                    });
+                        _ => url,
+                    };
+                    ui.colored_label(egui::Color32::from_rgb(0, 150, 0), &url);
+                    if ui.button("复制").clicked() {
+                        ui.ctx().copy_text(url.clone());
+                    }
+                    if ui.button("打开").clicked() {
+                        tokio::spawn(async move {
+                            if let Err(e) = open::that(&url) {
+                                log::error!("Failed to open browser: {}", e);
+                            }
+                        });
+                    }
+                }
+                (ListenerState::Failed(error), None) => {
+                    ui.colored_label(egui::Color32::from_rgb(150, 0, 0), error);
+                }
+                _ => {
+                    ui.colored_label(egui::Color32::GRAY, "启动中…");
                 }
             });
+
+            // The separate WebSocket listener only shows up when it is configured
+            match (
+                &server_status.websocket,
+                server_status.separate_websocket_url(),
+            ) {
+                (ListenerState::Disabled, _) => {}
+                (_, Some(url)) => {
+                    ui.horizontal(|ui| {
+                        ui.label("WebSocket:");
+                        ui.colored_label(egui::Color32::from_rgb(0, 150, 0), url);
+                    });
+                }
+                (ListenerState::Failed(error), None) => {
+                    ui.horizontal(|ui| {
+                        ui.label("WebSocket:");
+                        ui.colored_label(egui::Color32::from_rgb(150, 0, 0), error);
+                    });
+                }
+                _ => {}
+            }
+
+            self.render_overlay_status(ui);
         });
 
-        // Timer countdown display
-        if self.is_running {
-            ui.separator();
+        // Per-group timers with their own start/stop and countdown
+        ui.separator();
+        for group_state in &timer_state.groups {
             ui.horizontal(|ui| {
-                ui.label("倒计时:");
-                if let Some(ref timer) = self.timer_service {
-                    if let Some(time_left) = timer.get_time_until_next_toast() {
-                        let total_seconds = time_left.as_secs();
-                        let minutes = total_seconds / 60;
-                        let seconds = total_seconds % 60;
-
-                        let countdown_text = if total_seconds == 0 {
-                            "发送中...".to_string()
-                        } else {
-                            format!("{}:{:02}", minutes, seconds)
-                        };
-
-                        let countdown_color = if total_seconds <= 10 {
-                            egui::Color32::from_rgb(255, 100, 100) // Red when close
-                        } else if total_seconds <= 30 {
-                            egui::Color32::from_rgb(255, 200, 100) // Orange when getting close
-                        } else {
-                            egui::Color32::from_rgb(100, 200, 100) // Green when plenty of time
-                        };
-
-                        ui.colored_label(countdown_color, countdown_text);
-                    } else {
-                        ui.colored_label(egui::Color32::GRAY, "倒计时未开始");
+                ui.label(&group_state.group_name);
+
+                if group_state.is_running {
+                    if ui.small_button("停止").clicked() {
+                        self.stop_service(Some(group_state.group_id.clone()));
                     }
+                    render_countdown(ui, group_state);
                 } else {
-                    ui.colored_label(egui::Color32::GRAY, "倒计时未启用");
+                    if ui
+                        .add_enabled(!has_errors, egui::Button::new("开始").small())
+                        .clicked()
+                    {
+                        self.start_service(Some(group_state.group_id.clone()));
+                    }
+                    ui.colored_label(egui::Color32::GRAY, "已停止");
                 }
             });
         }
     }
 
-    fn start_service(&mut self) {
-        if let Err(e) = self.config.validate() {
-            log::error!("Configuration validation failed: {}", e);
+    /// Connected browser sources and what they reported for the latest toast.
+    fn render_overlay_status(&self, ui: &mut egui::Ui) {
+        let registry = self.services.websocket_server.overlays();
+        let overlays = registry.overlays();
+
+        if overlays.is_empty() {
+            ui.colored_label(egui::Color32::from_rgb(150, 0, 0), "没有已连接的浏览器源");
+        } else {
+            let names: Vec<String> = overlays
+                .iter()
+                .map(|o| match &o.channel {
+                    Some(channel) => format!("{} @{}", o.name, channel),
+                    None => o.name.clone(),
+                })
+                .collect();
+            ui.label(format!(
+                "已连接浏览器源: {}（{}）",
+                overlays.len(),
+                names.join("、")
+            ));
+        }
+
+        let queue = self.services.toast_queue.state();
+        if queue.pending > 0 {
+            ui.label(format!(
+                "提示队列: 显示中 {}，等待 {}",
+                queue.visible, queue.pending
+            ));
+        }
+
+        let Some(receipt) = registry.latest_receipt() else {
             return;
+        };
+
+        let delivered_color = if receipt.shown() < receipt.delivered() {
+            egui::Color32::from_rgb(200, 150, 0)
+        } else {
+            egui::Color32::from_rgb(0, 150, 0)
+        };
+        ui.colored_label(
+            delivered_color,
+            format!(
+                "最近提示「{}」已显示于 {}/{} 个浏览器源（{}）",
+                receipt.title,
+                receipt.shown(),
+                receipt.delivered(),
+                receipt.sent_at.format("%H:%M:%S")
+            ),
+        );
+
+        for failure in &receipt.audio_failures {
+            ui.colored_label(
+                egui::Color32::from_rgb(200, 150, 0),
+                format!(
+                    "⚠ {} 无法播放提示音（{}），请在 OBS 浏览器源属性中勾选“通过 OBS 控制音频”",
+                    failure.overlay, failure.reason
+                ),
+            );
         }
+    }
 
-        self.is_running = true;
+    fn start_service(&mut self, group_id: Option<String>) {
+        // The buttons are disabled and list the errors while there are any
+        if let Err(e) = self.config.validate() {
+            log::warn!("Not starting, the configuration is invalid: {}", e);
+            return;
+        }
 
         // Update timer service with current config
-        if let Some(ref mut timer) = self.timer_service {
-            timer.update_config(&self.config);
-            timer.start();
-        }
+        self.services.apply_config(self.config.clone());
+        self.services.timer.start(group_id);
 
         self.connection_status = "Timer Running".to_string();
         log::info!("Timer service started");
     }
 
-    fn stop_service(&mut self) {
-        self.is_running = false;
-
+    fn stop_service(&mut self, group_id: Option<String>) {
         // Stop timer service
-        if let Some(ref mut timer) = self.timer_service {
-            timer.stop();
-        }
+        self.services.timer.stop(group_id);
 
         self.connection_status = "WebSocket Ready".to_string();
         log::info!("Service stopped");
     }
 
     fn send_test_toast(&mut self) {
-        if self.config.toaster.titles.is_empty() || self.config.toaster.contents.is_empty() {
-            log::warn!("Cannot send test toast: no titles or contents configured");
+        let Some(group) = self.config.groups().get(self.selected_group) else {
             return;
-        }
+        };
 
-        let title = &self.config.toaster.titles[0];
-        let content = &self.config.toaster.contents[0];
+        let Some(reminder) = group.enabled_reminders().first().copied() else {
+            log::warn!("Cannot send test toast: no enabled reminders configured");
+            return;
+        };
+
+        // Filled in like a timed toast, with the countdown of the group if it is running
+        let next_in = self
+            .services
+            .timer
+            .state()
+            .groups
+            .iter()
+            .find(|state| state.group_id == group.id)
+            .and_then(GroupTimerState::time_until_next_toast);
+        let context = TemplateContext {
+            now: chrono::Local::now(),
+            stream_uptime: None,
+            count: 1,
+            next_in,
+        };
+        let mut rng = rand::thread_rng();
+        let title = template::expand(&reminder.title, &context, &mut rng);
+        let content = template::expand(&reminder.content, &context, &mut rng);
 
         log::info!(
             "Sending test toast - Title: {}, Content: {}",
@@ -491,115 +1034,202 @@ impl ObsReminderApp {
             content
         );
 
-        if let Some(ref sender) = self.websocket_sender {
-            let sound_url = if self.config.toaster.enable_sound {
-                self.config.toaster.sound_file_id.as_ref().map(|id| {
-                    let url = format!("/audio/{}", id);
-                    log::info!("Generated test sound URL: {} for ID: {}", url, id);
-                    url
-                })
-            } else {
-                None
-            };
-
-            let direction = match self.config.toaster.toast_direction {
-                ToastDirection::Top => "top",
-                ToastDirection::Bottom => "bottom",
-                ToastDirection::Left => "left",
-                ToastDirection::Right => "right",
-            }
-            .to_string();
-
-            let message = WebSocketMessage::new_toast(
-                title.clone(),
-                content.clone(),
-                self.config.toaster.color_1.clone(),
-                self.config.toaster.color_2.clone(),
-                self.config.toaster.text_color.clone(),
-                self.config.toaster.duration,
-                self.config.toaster.enable_sound,
-                sound_url,
-                direction,
-            );
+        let toast = ToastMessage::from_config(group, title, content);
+        self.services.toast_queue.push(toast);
 
-            if let Err(e) = sender.send(message) {
-                log::error!("Failed to send test toast: {}", e);
-            } else {
-                log::info!("Test toast sent successfully");
+        // Set cooldown to 1.2 seconds
+        let cooldown_duration = std::time::Duration::from_millis(1200);
+        self.test_toast_cooldown = Some(std::time::Instant::now() + cooldown_duration);
+    }
 
-                // Set cooldown to 1.2 seconds
-                let cooldown_duration = std::time::Duration::from_millis(1200);
-                self.test_toast_cooldown = Some(std::time::Instant::now() + cooldown_duration);
+    /// Saves the window's configuration with another active profile and applies it.
+    fn switch_profile(&mut self, name: &str) -> bool {
+        match self.services.switch_profile(self.config.clone(), name) {
+            Ok(()) => {
+                let config = self.services.config.borrow().clone();
+                self.load_config(config);
+                self.selected_group = 0;
+                true
+            }
+            Err(e) => {
+                log::error!("Failed to switch to profile {}: {}", name, e);
+                false
             }
-        } else {
-            log::error!("WebSocket sender not available");
         }
     }
 
-    fn send_automatic_toast(&mut self) {
-        if self.config.toaster.titles.is_empty() || self.config.toaster.contents.is_empty() {
-            log::warn!("Cannot send automatic toast: no titles or contents configured");
+    /// Exports the profile with its sound files to a ZIP archive chosen by the user.
+    fn export_profile(&mut self, name: &str) {
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("方案包", &["zip"])
+            .set_file_name(format!("{}.zip", name))
+            .save_file()
+        else {
             return;
-        }
+        };
 
-        // Get next content from timer service
-        let (title, content) = if let Some(ref mut timer) = self.timer_service {
-            timer.get_next_content(&self.config.toaster.titles, &self.config.toaster.contents)
-        } else {
+        let result = AudioManager::new()
+            .map_err(Into::into)
+            .and_then(|audio_manager| {
+                bundle::export_profile(&self.config, name, &path, &audio_manager)
+            });
+        self.bundle_message = Some(match result {
+            Ok(()) => Ok(format!("已导出到 {}", path.display())),
+            Err(e) => {
+                log::error!("Failed to export profile {}: {}", name, e);
+                Err(format!("导出失败: {}", e))
+            }
+        });
+    }
+
+    /// Adds the profile of a bundle chosen by the user. Current edits are saved along with it.
+    fn import_bundle(&mut self) {
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("方案包", &["zip"])
+            .pick_file()
+        else {
             return;
         };
 
-        log::info!(
-            "Sending automatic toast - Title: {}, Content: {}",
-            title,
-            content
-        );
-
-        if let Some(ref sender) = self.websocket_sender {
-            let sound_url = if self.config.toaster.enable_sound {
-                self.config.toaster.sound_file_id.as_ref().map(|id| {
-                    let url = format!("/audio/{}", id);
-                    log::info!("Generated automatic sound URL: {} for ID: {}", url, id);
-                    url
+        let result = AudioManager::new()
+            .map_err(Into::into)
+            .and_then(|audio_manager| {
+                bundle::import_bundle(&path, &self.config, &audio_manager, |config| {
+                    self.services.save_config(config)
                 })
-            } else {
-                None
-            };
+            });
+        self.bundle_message = Some(match result {
+            Ok(summary) => {
+                let config = self.services.config.borrow().clone();
+                self.load_config(config);
+
+                let mut message = format!("已导入方案 {}", summary.profile);
+                if summary.renamed_profile {
+                    message.push_str("（已有同名方案，已重命名）");
+                }
+                if summary.new_group_ids > 0 {
+                    message.push_str(&format!(
+                        "，{} 个分组的 ID 已被占用，已重新生成",
+                        summary.new_group_ids
+                    ));
+                }
+                Ok(message)
+            }
+            Err(e) => {
+                log::error!("Failed to import {}: {}", path.display(), e);
+                Err(format!("导入失败，当前设置未改动: {}", e))
+            }
+        });
+    }
 
-            let direction = match self.config.toaster.toast_direction {
-                ToastDirection::Top => "top",
-                ToastDirection::Bottom => "bottom",
-                ToastDirection::Left => "left",
-                ToastDirection::Right => "right",
-            }
-            .to_string();
-
-            let message = WebSocketMessage::new_toast(
-                title,
-                content,
-                self.config.toaster.color_1.clone(),
-                self.config.toaster.color_2.clone(),
-                self.config.toaster.text_color.clone(),
-                self.config.toaster.duration,
-                self.config.toaster.enable_sound,
-                sound_url,
-                direction,
-            );
+    fn load_config(&mut self, config: Config) {
+        self.saved_config = config.clone();
+        self.config = config;
+        self.external_config = None;
+        self.selected_group = self
+            .selected_group
+            .min(self.config.groups().len().saturating_sub(1));
+    }
 
-            if let Err(e) = sender.send(message) {
-                log::error!("Failed to send automatic toast: {}", e);
-            } else {
-                log::info!("Automatic toast sent successfully");
+    /// Asks whether to take a configuration changed outside the window or keep the unsaved edits.
+    fn render_external_change_prompt(&mut self, ctx: &egui::Context) {
+        let Some(external) = &self.external_config else {
+            return;
+        };
+
+        let mut load = false;
+        let mut keep = false;
+        egui::Window::new("配置已在外部修改")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .show(ctx, |ui| {
+                ui.label("config.toml 或 HTTP API 修改了配置，但窗口中还有未保存的更改。");
+                ui.label("新的配置已经生效；保留更改后点击“保存”会覆盖它。");
+                ui.horizontal(|ui| {
+                    load = ui.button("载入新配置（放弃我的更改）").clicked();
+                    keep = ui.button("保留我的更改").clicked();
+                });
+            });
+
+        if load {
+            let external = external.clone();
+            self.load_config(external);
+        } else if keep {
+            // Later outside changes are compared against the one just declined
+            self.saved_config = external.clone();
+            self.external_config = None;
+        }
+    }
+
+    /// Shown instead of the settings while config.toml is broken. Nothing is written to the
+    /// file until the user saves, and a fixed file is picked up by the config watcher.
+    fn render_load_error(&mut self, ui: &mut egui::Ui) {
+        let Some(error) = &self.load_error else {
+            return;
+        };
+
+        ui.heading("无法读取配置文件");
+        ui.label(format!("文件: {}", self.services.config_path));
+        ui.add_space(8.0);
+
+        if let (Some(line), Some(column)) = (error.line, error.column) {
+            ui.label(format!("第 {} 行，第 {} 列:", line, column));
+        }
+        // Reflects later edits to the file while it is still broken
+        let message = self
+            .services
+            .config_error
+            .borrow()
+            .clone()
+            .unwrap_or_else(|| error.message.clone());
+        ui.colored_label(
+            egui::Color32::from_rgb(200, 0, 0),
+            egui::RichText::new(message).monospace(),
+        );
+        ui.add_space(8.0);
+
+        match &error.backup_path {
+            Some(backup_path) => {
+                ui.label(format!(
+                    "原文件未被修改，并已备份到 {}",
+                    backup_path.display()
+                ));
+            }
+            None => {
+                ui.label("原文件未被修改。");
             }
-        } else {
-            log::error!("WebSocket sender not available for automatic toast");
+        }
+        ui.label("修正文件并保存后会自动重新载入。");
+        ui.add_space(8.0);
+
+        let mut use_defaults = false;
+        ui.horizontal(|ui| {
+            if ui.button("打开配置文件").clicked()
+                && let Err(e) = open::that(&self.services.config_path)
+            {
+                log::error!("Failed to open {}: {}", self.services.config_path, e);
+            }
+            use_defaults = ui.button("使用默认配置").clicked();
+        });
+        ui.colored_label(
+            egui::Color32::GRAY,
+            "使用默认配置不会覆盖文件，点击“保存”之后才会写入。",
+        );
+
+        if use_defaults {
+            self.load_error = None;
         }
     }
 
     fn save_configuration(&mut self) {
-        match self.config.save_to_file(&self.config_path) {
+        match self.services.save_config(self.config.clone()) {
             Ok(_) => {
-                log::info!("Configuration saved successfully to {}", self.config_path);
+                self.saved_config = self.config.clone();
+                log::info!(
+                    "Configuration saved successfully to {}",
+                    self.services.config_path
+                );
             }
             Err(e) => {
                 log::error!("Failed to save configuration: {}", e);
@@ -617,17 +1247,38 @@ impl eframe::App for ObsReminderApp {
             self.test_toast_cooldown = None;
         }
 
-        // Check if it's time to send a toast
-        if self.is_running
-            && let Some(ref mut timer) = self.timer_service
-            && timer.should_send_toast()
-        {
-            self.send_automatic_toast();
+        // Pick up configuration changes made outside the window (config.toml or the HTTP API)
+        if self.config_updates.has_changed().unwrap_or(false) {
+            let incoming = self.config_updates.borrow_and_update().clone();
+            let has_unsaved_edits = !self.config.same_as(&self.saved_config);
+
+            // Configurations applied by the window itself come back here as well
+            if !self.config.same_as(&incoming) {
+                if has_unsaved_edits {
+                    self.external_config = Some(incoming);
+                } else {
+                    self.load_config(incoming);
+                }
+            }
         }
 
+        // The file was fixed on disk and has been loaded
+        if self.load_error.is_some() && self.services.config_error.borrow().is_none() {
+            self.load_error = None;
+        }
+
+        self.render_external_change_prompt(ctx);
+        self.validation = self.config.check();
+
         egui::CentralPanel::default().show(ctx, |ui| {
+            if self.load_error.is_some() {
+                self.render_load_error(ui);
+                return;
+            }
+
             egui::ScrollArea::vertical().show(ui, |ui| {
                 self.render_header(ui);
+                self.render_groups(ui);
                 self.render_settings(ui);
                 self.render_controls(ui);
             });
@@ -638,8 +1289,158 @@ impl eframe::App for ObsReminderApp {
     }
 }
 
+/// Gives the groups of a copied profile their own sound files, since deleting or replacing a
+/// group's sound removes the cached file.
+fn copy_sound_files(profile: &mut Profile) {
+    let Ok(audio_manager) = AudioManager::new() else {
+        return;
+    };
+
+    for group in &mut profile.groups {
+        let Some(path) = group
+            .sound_file_id
+            .as_deref()
+            .and_then(|file_id| audio_manager.get_audio_file_path(file_id))
+        else {
+            continue;
+        };
+
+        match audio_manager.add_audio_file(&path) {
+            Ok(file_id) => group.sound_file_id = Some(file_id),
+            Err(e) => log::error!("Failed to copy audio file {}: {}", path.display(), e),
+        }
+    }
+}
+
+fn switch_mode_label(mode: &ContentSwitchMode) -> &'static str {
+    match mode {
+        ContentSwitchMode::Random => "随机",
+        ContentSwitchMode::Sequential => "顺序",
+        ContentSwitchMode::Shuffle => "洗牌 (每轮不重复)",
+        ContentSwitchMode::Weighted => "加权随机",
+        ContentSwitchMode::LeastRecent => "最久未显示",
+    }
+}
+
+fn missed_policy_label(policy: MissedToastPolicy) -> &'static str {
+    match policy {
+        MissedToastPolicy::Skip => "跳过",
+        MissedToastPolicy::FireOnce => "启动后补发一次",
+    }
+}
+
+fn overlap_policy_label(policy: OverlapPolicy) -> &'static str {
+    match policy {
+        OverlapPolicy::Stack => "叠加显示",
+        OverlapPolicy::Queue => "依次排队",
+        OverlapPolicy::Replace => "替换当前提示",
+        OverlapPolicy::Drop => "忙碌时丢弃",
+    }
+}
+
+fn priority_label(priority: Priority) -> &'static str {
+    match priority {
+        Priority::Low => "低",
+        Priority::Normal => "普通",
+        Priority::High => "高",
+    }
+}
+
+fn text_format_label(format: TextFormat) -> &'static str {
+    match format {
+        TextFormat::Plain => "纯文本",
+        TextFormat::Markup => "简单标记",
+        TextFormat::Markdown => "Markdown",
+    }
+}
+
+fn schedule_mode_label(mode: ScheduleMode) -> &'static str {
+    match mode {
+        ScheduleMode::Interval => "固定间隔",
+        ScheduleMode::Hourly => "每小时",
+        ScheduleMode::Daily => "每天",
+    }
+}
+
+fn weekday_label(weekday: Weekday) -> &'static str {
+    match weekday {
+        Weekday::Mon => "一",
+        Weekday::Tue => "二",
+        Weekday::Wed => "三",
+        Weekday::Thu => "四",
+        Weekday::Fri => "五",
+        Weekday::Sat => "六",
+        Weekday::Sun => "日",
+    }
+}
+
+/// Shows what is wrong with a field right below it: errors in red, warnings in amber.
+fn render_issues<'a>(ui: &mut egui::Ui, issues: impl Iterator<Item = &'a ConfigIssue>) {
+    for issue in issues {
+        let color = match issue.severity() {
+            Severity::Error => egui::Color32::from_rgb(200, 0, 0),
+            Severity::Warning => egui::Color32::from_rgb(200, 150, 0),
+        };
+        ui.colored_label(color, format!("⚠ {}", issue_label(&issue.kind)));
+    }
+}
+
+fn issue_label(kind: &IssueKind) -> String {
+    match kind {
+        IssueKind::Empty => "不能为空".to_string(),
+        IssueKind::OutOfRange { min, max } => format!("应在 {} 到 {} 之间", min, max),
+        IssueKind::InvalidColor => "不是有效的颜色，例如 #FF6B6B".to_string(),
+        IssueKind::InvalidChannel => "频道名只能使用字母、数字、- 和 _".to_string(),
+        IssueKind::InvalidTime => "不是有效的时间，例如 21:00".to_string(),
+        IssueKind::Duplicate => "与前面的项重复".to_string(),
+        IssueKind::UnknownProfile => "没有这个名称的方案".to_string(),
+        IssueKind::NothingEnabled => "至少需要启用一条提醒".to_string(),
+        IssueKind::SameAs(field) => format!("不能与 {} 相同", field),
+        IssueKind::Template(error) => format!("变量有误：{}", error),
+        IssueKind::UnknownVariable(name) => format!("没有变量 {{{}}}，将按原样显示", name),
+        IssueKind::TooLong { length, max } => {
+            format!("共 {} 个字，提示框大约只能完整显示 {} 个字", length, max)
+        }
+        IssueKind::NoSoundFile => "已启用音效，但还没有选择音效文件".to_string(),
+    }
+}
+
+fn render_countdown(ui: &mut egui::Ui, group_state: &GroupTimerState) {
+    let Some(time_left) = group_state.time_until_next_toast() else {
+        ui.colored_label(egui::Color32::GRAY, "倒计时未开始");
+        return;
+    };
+
+    let total_seconds = time_left.as_secs();
+    let hours = total_seconds / 3600;
+    let minutes = total_seconds % 3600 / 60;
+    let seconds = total_seconds % 60;
+
+    let countdown_text = if total_seconds == 0 {
+        "发送中...".to_string()
+    } else if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, seconds)
+    } else {
+        format!("{}:{:02}", minutes, seconds)
+    };
+
+    let countdown_color = if total_seconds <= 10 {
+        egui::Color32::from_rgb(255, 100, 100) // Red when close
+    } else if total_seconds <= 30 {
+        egui::Color32::from_rgb(255, 200, 100) // Orange when getting close
+    } else {
+        egui::Color32::from_rgb(100, 200, 100) // Green when plenty of time
+    };
+
+    ui.colored_label(countdown_color, countdown_text);
+
+    if let Some(next_time) = group_state.next_toast_time {
+        ui.label(format!("(下次: {})", next_time.format("%m-%d %H:%M:%S")));
+    }
+}
+
 // Helper functions for color conversion
-fn hex_to_color32(hex: &str) -> egui::Color32 {
+pub fn hex_to_color32(hex: &str) -> egui::Color32 {
     if hex.len() != 7 || !hex.starts_with('#') {
         return egui::Color32::from_rgb(255, 107, 107); // Default fallback color
     }
@@ -2,6 +2,9 @@ use std::fs;
 use std::path::{Path, PathBuf};
 use uuid::Uuid;
 
+/// Extensions of cached sound files; the file name is the UUID plus one of these.
+pub const AUDIO_EXTENSIONS: [&str; 4] = ["mp3", "wav", "ogg", "m4a"];
+
 #[derive(Debug)]
 pub enum AudioError {
     IoError(std::io::Error),
@@ -29,7 +32,12 @@ pub struct AudioManager {
 
 impl AudioManager {
     pub fn new() -> Result<Self, AudioError> {
-        let cache_dir = PathBuf::from("audio_cache");
+        Self::with_dir("audio_cache")
+    }
+
+    /// Uses another cache directory than `audio_cache` in the working directory.
+    pub fn with_dir<P: Into<PathBuf>>(cache_dir: P) -> Result<Self, AudioError> {
+        let cache_dir = cache_dir.into();
 
         // Create cache directory if it doesn't exist
         if !cache_dir.exists() {
@@ -67,11 +75,24 @@ impl AudioManager {
         Ok(file_id)
     }
 
+    /// Caches sound data that does not come from a file, e.g. from an imported bundle.
+    pub fn add_audio_data(&self, contents: &[u8], extension: &str) -> Result<String, AudioError> {
+        let file_id = Uuid::new_v4().to_string();
+        let dest_path = self.cache_dir.join(format!("{}.{}", file_id, extension));
+        fs::write(&dest_path, contents)?;
+
+        log::info!("Audio file cached: {}", dest_path.display());
+        Ok(file_id)
+    }
+
     pub fn get_audio_file_path(&self, file_id: &str) -> Option<PathBuf> {
-        // Try different extensions
-        let extensions = ["mp3", "wav", "ogg", "m4a"];
+        // Ids end up in a path, so anything else could point outside the cache
+        if !is_file_id(file_id) {
+            return None;
+        }
 
-        for ext in &extensions {
+        // Try different extensions
+        for ext in &AUDIO_EXTENSIONS {
             let filename = format!("{}.{}", file_id, ext);
             let path = self.cache_dir.join(&filename);
             if path.exists() {
@@ -111,3 +132,28 @@ impl AudioManager {
         Ok(())
     }
 }
+
+/// Cached files are named after the UUID they were given when added.
+pub fn is_file_id(file_id: &str) -> bool {
+    Uuid::try_parse(file_id).is_ok_and(|id| id.hyphenated().to_string() == file_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_uuids_are_file_ids() {
+        assert!(is_file_id(&Uuid::new_v4().to_string()));
+        for id in [
+            "",
+            "..",
+            "../config",
+            "..%2Fconfig",
+            "{67e55044-10b1-426f-9247-bb680e5fe0c8}",
+            "67e55044-10b1-426f-9247-bb680e5fe0c8/../x",
+        ] {
+            assert!(!is_file_id(id), "{}", id);
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use crate::api::query_param;
+use hyper::HeaderMap;
+use hyper::header::{AUTHORIZATION, HOST, ORIGIN};
+
+/// Query parameter carrying the token, for clients that cannot set headers (OBS browser sources).
+pub const TOKEN_PARAM: &str = "token";
+
+/// Checks the shared secret, sent either as `Authorization: Bearer <token>` or as `?token=`.
+/// Everything is allowed when no token is configured.
+pub fn is_authorized(token: Option<&str>, headers: &HeaderMap, query: Option<&str>) -> bool {
+    let Some(token) = token.filter(|t| !t.is_empty()) else {
+        return true;
+    };
+
+    let from_header = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|value| value.trim().to_string());
+    let from_query = query_param(query, TOKEN_PARAM);
+
+    [from_header, from_query]
+        .into_iter()
+        .flatten()
+        .any(|given| constant_time_eq(given.as_bytes(), token.as_bytes()))
+}
+
+/// Browsers apply no CORS to WebSocket handshakes and send simple cross-site POSTs without a
+/// preflight, so any open page could otherwise push toasts or control the timers. Clients
+/// without an `Origin` (the CLI, scripts) are allowed, and so are pages of our own HTTP server:
+/// the host the request was sent to, on the port the overlay is served on.
+///
+/// Comparing `Origin` to `Host` does not stop DNS rebinding, where a foreign page is served
+/// under a name that resolves to this machine and so matches both. Without a token that is
+/// open to any page, so binds beyond loopback still need one.
+pub fn is_own_origin(headers: &HeaderMap, http_port: Option<u16>) -> bool {
+    let Some(origin) = headers.get(ORIGIN) else {
+        return true;
+    };
+    let Some(origin) = origin
+        .to_str()
+        .ok()
+        .and_then(|origin| origin.strip_prefix("http://"))
+    else {
+        return false; // other schemes, including the "null" origin of local files
+    };
+    let Some(host) = headers.get(HOST).and_then(|host| host.to_str().ok()) else {
+        return false;
+    };
+
+    let (origin_host, origin_port) = split_authority(origin);
+    let (host, _) = split_authority(host);
+    origin_host.eq_ignore_ascii_case(host) && http_port == Some(origin_port)
+}
+
+pub fn generate_token() -> String {
+    uuid::Uuid::new_v4().simple().to_string()
+}
+
+/// Compares without returning early so the time taken does not reveal the matching prefix.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Splits `host:port`, `[::1]:port` or a bare host, which is on port 80.
+fn split_authority(authority: &str) -> (&str, u16) {
+    match authority.rsplit_once(':') {
+        Some((host, port)) if !host.is_empty() && !port.contains(']') => {
+            (host, port.parse().unwrap_or(0))
+        }
+        _ => (authority, 80),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn handshake(host: &str, origin: Option<&str>) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(HOST, host.parse().unwrap());
+        if let Some(origin) = origin {
+            headers.insert(ORIGIN, origin.parse().unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn only_the_overlay_page_may_connect_from_a_browser() {
+        let port = Some(8080);
+        assert!(is_own_origin(&handshake("localhost:8080", None), port));
+        assert!(is_own_origin(
+            &handshake("localhost:8080", Some("http://localhost:8080")),
+            port
+        ));
+        // The separate WebSocket listener is reached from the page on the HTTP port
+        assert!(is_own_origin(
+            &handshake("192.168.1.5:7981", Some("http://192.168.1.5:8080")),
+            port
+        ));
+        assert!(is_own_origin(
+            &handshake("[::1]:8080", Some("http://[::1]:8080")),
+            port
+        ));
+
+        for origin in [
+            "https://example.com",
+            "http://example.com:8080",
+            "http://localhost:5173",
+            "null",
+        ] {
+            assert!(
+                !is_own_origin(&handshake("localhost:8080", Some(origin)), port),
+                "{}",
+                origin
+            );
+        }
+    }
+}
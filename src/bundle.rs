@@ -0,0 +1,479 @@
+use crate::audio_manager::{AUDIO_EXTENSIONS, AudioManager};
+use crate::config::{Config, Profile, ToasterConfig, new_group_id};
+use crate::config_migration::{SCHEMA_VERSION, migrate_profile};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{Cursor, Read, Write};
+use std::path::Path;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+type BundleError = Box<dyn std::error::Error>;
+
+/// Layout of the archive, checked on import.
+const BUNDLE_FORMAT: u32 = 1;
+const METADATA_FILE: &str = "bundle.toml";
+const PROFILE_FILE: &str = "profile.toml";
+const AUDIO_FOLDER: &str = "audio/";
+
+/// Bundles are read into memory, so keep them, packed and extracted, to a size sound files
+/// never come close to.
+const MAX_BUNDLE_SIZE: u64 = 100 * 1024 * 1024;
+
+/// More files than any bundle needs.
+const MAX_ENTRIES: usize = 1000;
+
+/// `bundle.toml`: where a bundle comes from and what it contains.
+#[derive(Debug, Serialize, Deserialize)]
+struct BundleMetadata {
+    format: u32,
+    schema_version: u32, // config schema of profile.toml
+    app_version: String,
+    exported_at: DateTime<Local>,
+    profile: String,
+    /// Group id to the path of its sound file in the archive. `sound_file_id` stays empty in
+    /// profile.toml since it names a file in the audio cache.
+    #[serde(default)]
+    sounds: BTreeMap<String, String>,
+}
+
+/// What an import added to the configuration.
+#[derive(Debug)]
+pub struct ImportSummary {
+    pub profile: String, // the name it was added under, which may differ from the bundle
+    pub renamed_profile: bool,
+    pub new_group_ids: usize, // groups whose id was already taken
+    pub audio_files: usize,
+}
+
+/// Writes a profile, the sound files of its groups and metadata to a ZIP archive. Sound files
+/// are stored as `audio/<original name>` and listed by group in `bundle.toml`.
+pub fn export_profile(
+    config: &Config,
+    name: &str,
+    path: &Path,
+    audio_manager: &AudioManager,
+) -> Result<(), BundleError> {
+    let mut profile = config
+        .profile(name)
+        .ok_or_else(|| format!("Unknown profile: {}", name))?
+        .clone();
+
+    let mut audio: Vec<(String, Vec<u8>)> = Vec::new(); // bundle path and contents
+    let mut exported: HashMap<String, String> = HashMap::new(); // cache id to bundle path
+    let mut sounds = BTreeMap::new();
+    for group in &mut profile.groups {
+        let Some(file_id) = group.sound_file_id.take() else {
+            continue;
+        };
+        if let Some(entry) = exported.get(&file_id) {
+            sounds.insert(group.id.clone(), entry.clone());
+            continue;
+        }
+        let Some(source) = audio_manager.get_audio_file_path(&file_id) else {
+            log::warn!(
+                "Sound file of group {} is missing, exporting the group without it",
+                group.name
+            );
+            group.sound_file_name = None;
+            continue;
+        };
+
+        let entry = audio_entry_name(group, &source, &audio);
+        audio.push((entry.clone(), fs::read(&source)?));
+        sounds.insert(group.id.clone(), entry.clone());
+        exported.insert(file_id, entry);
+    }
+
+    let metadata = BundleMetadata {
+        format: BUNDLE_FORMAT,
+        schema_version: SCHEMA_VERSION,
+        app_version: std::env!("CARGO_PKG_VERSION").to_string(),
+        exported_at: Local::now(),
+        profile: profile.name.clone(),
+        sounds,
+    };
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    zip.start_file(METADATA_FILE, options)?;
+    zip.write_all(toml::to_string_pretty(&metadata)?.as_bytes())?;
+    zip.start_file(PROFILE_FILE, options)?;
+    zip.write_all(toml::to_string_pretty(&profile)?.as_bytes())?;
+    for (entry, contents) in &audio {
+        // Sound files are compressed already
+        zip.start_file(
+            entry.as_str(),
+            options.compression_method(CompressionMethod::Stored),
+        )?;
+        zip.write_all(contents)?;
+    }
+    let data = zip.finish()?.into_inner();
+
+    // Write to a temporary file first so a failed export never leaves half a bundle
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    fs::write(&tmp_path, data)?;
+    fs::rename(&tmp_path, path)?;
+
+    log::info!(
+        "Profile {} exported to {} with {} sound file(s)",
+        profile.name,
+        path.display(),
+        audio.len()
+    );
+    Ok(())
+}
+
+/// Adds the profile of a bundle to a copy of `config` and hands it to `save`. A taken profile
+/// name gets a number and taken group ids are replaced. Sound files are cached before saving and
+/// removed again if anything fails, so a failed import leaves the current setup untouched.
+pub fn import_bundle(
+    path: &Path,
+    config: &Config,
+    audio_manager: &AudioManager,
+    save: impl FnOnce(Config) -> Result<(), BundleError>,
+) -> Result<ImportSummary, BundleError> {
+    if fs::metadata(path)?.len() > MAX_BUNDLE_SIZE {
+        return Err(format!("{} is too large for a bundle", path.display()).into());
+    }
+    let files = read_archive(&fs::read(path)?, MAX_BUNDLE_SIZE)?;
+    let text = |name: &str| -> Result<String, BundleError> {
+        let contents = files
+            .get(name)
+            .ok_or_else(|| format!("{} is not a bundle: {} is missing", path.display(), name))?;
+        Ok(String::from_utf8(contents.clone())?)
+    };
+
+    let metadata: BundleMetadata = toml::from_str(&text(METADATA_FILE)?)
+        .map_err(|e| format!("Invalid {}: {}", METADATA_FILE, e.message()))?;
+    if metadata.format > BUNDLE_FORMAT || metadata.schema_version > SCHEMA_VERSION {
+        return Err(format!(
+            "The bundle was exported by OBS Reminder {}, which is newer than this version",
+            metadata.app_version
+        )
+        .into());
+    }
+
+    // Bundles of older versions are migrated like config.toml
+    let profile: toml::Table = toml::from_str(&text(PROFILE_FILE)?)
+        .map_err(|e| format!("Invalid {}: {}", PROFILE_FILE, e.message()))?;
+    let mut profile: Profile = toml::Value::Table(
+        migrate_profile(profile, metadata.schema_version)
+            .map_err(|e| format!("Invalid {}: {}", PROFILE_FILE, e))?,
+    )
+    .try_into()
+    .map_err(|e: toml::de::Error| format!("Invalid {}: {}", PROFILE_FILE, e.message()))?;
+
+    // Sounds are looked up by the ids the groups had when exported
+    let group_sounds: Vec<Option<&String>> = profile
+        .groups
+        .iter()
+        .map(|group| metadata.sounds.get(&group.id))
+        .collect();
+
+    // Resolve conflicts with the current setup
+    let bundle_name = profile.name.clone();
+    for i in 2.. {
+        if config.profile(&profile.name).is_none() {
+            break;
+        }
+        profile.name = format!("{} ({})", bundle_name, i);
+    }
+    let mut taken_ids: Vec<String> = config.all_groups().map(|g| g.id.clone()).collect();
+    let mut new_group_ids = 0;
+    for group in &mut profile.groups {
+        if taken_ids.contains(&group.id) {
+            group.id = new_group_id();
+            new_group_ids += 1;
+        }
+        taken_ids.push(group.id.clone());
+        group.sound_file_id = None; // only ever a file of the audio cache
+    }
+
+    // Check everything before anything is written
+    let mut sounds = Vec::new(); // group index, bundle path and extension
+    for (index, entry) in group_sounds.into_iter().enumerate() {
+        let Some(entry) = entry else {
+            continue;
+        };
+        let extension = Path::new(entry)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_lowercase)
+            .filter(|extension| AUDIO_EXTENSIONS.contains(&extension.as_str()))
+            .ok_or_else(|| format!("Unsupported sound file {}", entry))?;
+        if !files.contains_key(entry) {
+            return Err(format!("Sound file {} is missing from the bundle", entry).into());
+        }
+        sounds.push((index, entry.clone(), extension));
+    }
+
+    let mut new_config = config.clone();
+    new_config.profiles.push(profile);
+    new_config
+        .validate()
+        .map_err(|e| format!("The bundle is invalid: {}", e))?;
+
+    // A file shared by several groups is cached once
+    let mut cached: HashMap<String, String> = HashMap::new(); // bundle path to cache id
+    let remove_cached = |cached: &HashMap<String, String>| {
+        for file_id in cached.values() {
+            let _ = audio_manager.remove_audio_file(file_id);
+        }
+    };
+
+    let profile_index = new_config.profiles.len() - 1;
+    for (index, entry, extension) in sounds {
+        let file_id = match cached.get(&entry) {
+            Some(file_id) => file_id.clone(),
+            None => match audio_manager.add_audio_data(&files[&entry], &extension) {
+                Ok(file_id) => {
+                    cached.insert(entry.clone(), file_id.clone());
+                    file_id
+                }
+                Err(e) => {
+                    remove_cached(&cached);
+                    return Err(e.into());
+                }
+            },
+        };
+
+        let group = &mut new_config.profiles[profile_index].groups[index];
+        group.sound_file_id = Some(file_id);
+        if group.sound_file_name.is_none() {
+            group.sound_file_name = entry.strip_prefix(AUDIO_FOLDER).map(str::to_string);
+        }
+    }
+
+    let summary = ImportSummary {
+        profile: new_config.profiles[profile_index].name.clone(),
+        renamed_profile: new_config.profiles[profile_index].name != bundle_name,
+        new_group_ids,
+        audio_files: cached.len(),
+    };
+    if let Err(e) = save(new_config) {
+        remove_cached(&cached);
+        return Err(e);
+    }
+
+    log::info!(
+        "Imported profile {} from {} with {} sound file(s)",
+        summary.profile,
+        path.display(),
+        summary.audio_files
+    );
+    Ok(summary)
+}
+
+/// Extracts every file of an archive by name. Paths leading outside the archive, too many files
+/// and more than `max_size` bytes once extracted are refused.
+fn read_archive(data: &[u8], max_size: u64) -> Result<HashMap<String, Vec<u8>>, BundleError> {
+    let mut archive = ZipArchive::new(Cursor::new(data))?;
+    if archive.len() > MAX_ENTRIES {
+        return Err(format!("The bundle has more than {} files", MAX_ENTRIES).into());
+    }
+
+    let mut files = HashMap::new();
+    let mut remaining = max_size;
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        if file.enclosed_name().is_none() {
+            return Err(format!("{} points outside the bundle", file.name()).into());
+        }
+        if file.is_dir() {
+            continue;
+        }
+
+        // The sizes in the archive are not trusted, only what the decompressor produces
+        let mut contents = Vec::new();
+        (&mut file).take(remaining + 1).read_to_end(&mut contents)?;
+        if contents.len() as u64 > remaining {
+            return Err("The bundle is too large once extracted".into());
+        }
+        remaining -= contents.len() as u64;
+        files.insert(file.name().to_string(), contents);
+    }
+    Ok(files)
+}
+
+/// `audio/<original name>`, limited to characters that are safe in file names and numbered if
+/// another sound already uses the name.
+fn audio_entry_name(group: &ToasterConfig, source: &Path, taken: &[(String, Vec<u8>)]) -> String {
+    let extension = source
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or("mp3");
+    let stem = group
+        .sound_file_name
+        .as_deref()
+        .and_then(|name| Path::new(name).file_stem())
+        .and_then(|stem| stem.to_str())
+        .unwrap_or(&group.name);
+    let stem: String = stem
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || " -_().".contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let stem = match stem.trim() {
+        "" => "sound",
+        stem => stem,
+    };
+
+    let mut entry = format!("{}{}.{}", AUDIO_FOLDER, stem, extension);
+    for i in 2.. {
+        if !taken.iter().any(|(taken, _)| *taken == entry) {
+            break;
+        }
+        entry = format!("{}{} ({}).{}", AUDIO_FOLDER, stem, i, extension);
+    }
+    entry
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// A profile whose first group plays a sound, exported from its own audio cache.
+    fn export(dir: &TempDir) -> (Config, std::path::PathBuf) {
+        let source = AudioManager::with_dir(dir.path().join("source")).unwrap();
+        let mut config = Config::default();
+        let group = &mut config.profiles[0].groups[0];
+        group.enable_sound = true;
+        group.sound_file_id = Some(source.add_audio_data(b"ID3", "mp3").unwrap());
+        group.sound_file_name = Some("叮 ding.mp3".to_string());
+
+        let path = dir.path().join("bundle.zip");
+        export_profile(&config, &config.profiles[0].name, &path, &source).unwrap();
+        (config, path)
+    }
+
+    fn files_in(dir: &Path) -> usize {
+        fs::read_dir(dir).map_or(0, |dir| dir.count())
+    }
+
+    #[test]
+    fn exported_profiles_import_with_their_sounds() {
+        let dir = tempfile::tempdir().unwrap();
+        let (exported, path) = export(&dir);
+
+        // The archive names its sounds in bundle.toml, never as cache ids
+        let files = read_archive(&fs::read(&path).unwrap(), MAX_BUNDLE_SIZE).unwrap();
+        let profile = String::from_utf8(files[PROFILE_FILE].clone()).unwrap();
+        assert!(!profile.contains("sound_file_id"), "{}", profile);
+        assert_eq!(files["audio/叮 ding.mp3"], b"ID3");
+
+        let cache = AudioManager::with_dir(dir.path().join("cache")).unwrap();
+        let mut saved = None;
+        let summary = import_bundle(&path, &exported, &cache, |config| {
+            saved = Some(config);
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(summary.profile, "默认 (2)");
+        assert!(summary.renamed_profile);
+        assert_eq!(summary.new_group_ids, 1);
+        assert_eq!(summary.audio_files, 1);
+        let saved = saved.unwrap();
+        let group = &saved.profile(&summary.profile).unwrap().groups[0];
+        let file_path = cache
+            .get_audio_file_path(group.sound_file_id.as_deref().unwrap())
+            .unwrap();
+        assert_eq!(fs::read(file_path).unwrap(), b"ID3");
+        assert_eq!(group.sound_file_name.as_deref(), Some("叮 ding.mp3"));
+    }
+
+    #[test]
+    fn failed_import_removes_the_cached_sounds() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_, path) = export(&dir);
+        let cache_dir = dir.path().join("cache");
+        let cache = AudioManager::with_dir(&cache_dir).unwrap();
+
+        let mut attempted = false;
+        let result = import_bundle(&path, &Config::default(), &cache, |_| {
+            attempted = true;
+            Err("disk full".into())
+        });
+        assert_eq!(result.unwrap_err().to_string(), "disk full");
+        assert!(attempted);
+        assert_eq!(files_in(&cache_dir), 0);
+    }
+
+    #[test]
+    fn bundles_of_other_schema_versions() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_, path) = export(&dir);
+        let files = read_archive(&fs::read(&path).unwrap(), MAX_BUNDLE_SIZE).unwrap();
+        let metadata = String::from_utf8(files[METADATA_FILE].clone()).unwrap();
+
+        let rewrite = |schema_version: u32| {
+            let metadata = metadata.replace(
+                &format!("schema_version = {}", SCHEMA_VERSION),
+                &format!("schema_version = {}", schema_version),
+            );
+            let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+            for (name, contents) in &files {
+                let contents = match name.as_str() {
+                    METADATA_FILE => metadata.as_bytes(),
+                    _ => contents,
+                };
+                zip.start_file(name.as_str(), SimpleFileOptions::default())
+                    .unwrap();
+                zip.write_all(contents).unwrap();
+            }
+            fs::write(&path, zip.finish().unwrap().into_inner()).unwrap();
+        };
+        let cache = AudioManager::with_dir(dir.path().join("cache")).unwrap();
+        let import = || import_bundle(&path, &Config::default(), &cache, |_| Ok(()));
+
+        rewrite(SCHEMA_VERSION + 1);
+        assert!(import().unwrap_err().to_string().contains("newer"));
+        rewrite(SCHEMA_VERSION - 1);
+        assert!(import().unwrap_err().to_string().contains("no profiles"));
+        rewrite(SCHEMA_VERSION);
+        assert!(import().is_ok());
+    }
+
+    #[test]
+    fn unsafe_archives_are_refused() {
+        let archive = |files: &[(&str, &[u8])]| {
+            let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+            let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+            for (name, contents) in files {
+                zip.start_file(*name, stored).unwrap();
+                zip.write_all(contents).unwrap();
+            }
+            zip.finish().unwrap().into_inner()
+        };
+
+        for name in ["../config.toml", "audio/../../x.mp3", "/etc/passwd"] {
+            let data = archive(&[(name, b"x")]);
+            assert!(read_archive(&data, 1024).is_err(), "{}", name);
+        }
+
+        // Limits hold for what is extracted
+        let data = archive(&[("a", &[0; 600]), ("b", &[0; 600])]);
+        assert!(read_archive(&data, 1200).is_ok());
+        assert!(read_archive(&data, 1000).is_err());
+
+        let names: Vec<String> = (0..=MAX_ENTRIES).map(|i| i.to_string()).collect();
+        let files: Vec<(&str, &[u8])> = names.iter().map(|n| (n.as_str(), &b""[..])).collect();
+        assert!(read_archive(&archive(&files), 1024).is_err());
+
+        let mut data = archive(&[("a.txt", b"hello")]);
+        let at = data.windows(5).position(|w| w == b"hello").unwrap();
+        data[at] ^= 1;
+        assert!(read_archive(&data, 1024).is_err());
+        assert!(read_archive(&data[..data.len() - 1], 1024).is_err());
+    }
+}
@@ -0,0 +1,369 @@
+use crate::audio_manager::AudioManager;
+use crate::auth::TOKEN_PARAM;
+use crate::bundle;
+use crate::config::{Config, ServerConfig, is_valid_channel_name};
+use crate::http_server::WEBSOCKET_PATH;
+use crate::services::Services;
+use crate::websocket::{ClientMessage, ClientRole, PROTOCOL_VERSION, ToastMessage};
+use clap::{Parser, Subcommand};
+use futures_util::SinkExt;
+use http_body_util::{BodyExt, Full};
+use hyper::body::Bytes;
+use hyper::header::{AUTHORIZATION, CONTENT_TYPE, HOST};
+use hyper::{Method, Request};
+use hyper_util::rt::TokioIo;
+use std::path::{Path, PathBuf};
+use tokio::net::TcpStream;
+use tokio_tungstenite::connect_async;
+
+type CliError = Box<dyn std::error::Error>;
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "obs-reminder",
+    version,
+    about = "Toast reminders for OBS browser sources"
+)]
+pub struct Cli {
+    /// Path to the configuration file
+    #[arg(short, long, global = true, default_value = "config.toml")]
+    pub config: String,
+
+    /// Run the servers and the timer without opening the window
+    #[arg(long)]
+    pub headless: bool,
+
+    /// With --headless, start every timer instead of resuming the ones that were running
+    #[arg(long, requires = "headless")]
+    pub start: bool,
+
+    #[command(flatten)]
+    pub server: ServerArgs,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Overrides for the `[server]` section; they apply to this run only and are never saved.
+#[derive(Debug, Clone, clap::Args)]
+pub struct ServerArgs {
+    /// Address the servers listen on, e.g. 0.0.0.0 to allow other machines
+    #[arg(long, global = true)]
+    pub host: Option<String>,
+
+    /// Port of the HTTP server serving the browser source
+    #[arg(long, global = true)]
+    pub http_port: Option<u16>,
+
+    /// Also accept WebSocket connections on this port (they are always accepted on `/ws`)
+    #[arg(long = "ws-port", global = true)]
+    pub websocket_port: Option<u16>,
+
+    /// Fail instead of using another port when a configured port is taken
+    #[arg(long, global = true)]
+    pub no_port_fallback: bool,
+}
+
+impl ServerArgs {
+    pub fn apply(&self, server: &ServerConfig) -> ServerConfig {
+        let mut server = server.clone();
+        if let Some(host) = &self.host {
+            server.host = host.clone();
+        }
+        if let Some(port) = self.http_port {
+            server.http_port = port;
+        }
+        if let Some(port) = self.websocket_port {
+            server.websocket_port = Some(port);
+        }
+        if self.no_port_fallback {
+            server.port_fallback = false;
+        }
+        server
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run headless: start the servers and the timer until SIGINT/SIGTERM
+    Run {
+        /// Start every timer instead of resuming the ones that were running
+        #[arg(long)]
+        start: bool,
+    },
+    /// Send a single toast through a running instance
+    Send {
+        /// Toast title
+        #[arg(short, long)]
+        title: String,
+        /// Toast content
+        #[arg(short = 'm', long)]
+        content: String,
+        /// Reminder group (id or name) whose style is used; defaults to the first group
+        #[arg(short, long)]
+        group: Option<String>,
+        /// Overlay channel to show the toast on; repeat for several, defaults to the group's channels
+        #[arg(long = "channel")]
+        channels: Vec<String>,
+    },
+    /// Switch to another profile, or list the profiles when no name is given. A running
+    /// instance switches live; otherwise the next start uses the profile.
+    Profile {
+        /// Name of the profile to switch to
+        name: Option<String>,
+    },
+    /// Write a profile and its sound files to a bundle (ZIP archive) to share or back up
+    Export {
+        /// Path of the bundle to write
+        path: PathBuf,
+        /// Profile to export; defaults to the active profile
+        #[arg(short, long)]
+        profile: Option<String>,
+    },
+    /// Add the profile of a bundle next to the existing ones. A running instance picks it up
+    /// from the configuration file.
+    Import {
+        /// Path of the bundle to read
+        path: PathBuf,
+    },
+    /// Check the configuration file and exit
+    ValidateConfig,
+}
+
+pub async fn run_headless(
+    config_path: &str,
+    server_args: &ServerArgs,
+    start: bool,
+) -> Result<(), CliError> {
+    log::info!(
+        "OBS Reminder v{} starting headless",
+        std::env!("CARGO_PKG_VERSION")
+    );
+
+    // Running with default reminders on stream would be worse than not starting
+    let (config, error) = Config::load_or_default(config_path);
+    if let Some(error) = error {
+        return Err(format!("Invalid configuration in {}: {}", config_path, error).into());
+    }
+    log::info!("Configuration loaded from {}", config_path);
+    for warning in config.check().warnings() {
+        log::warn!("{}", warning);
+    }
+
+    let services = Services::start(
+        &config,
+        None,
+        config_path,
+        &server_args.apply(&config.server),
+    );
+    // Like the window, resume the timers that were running when the app last exited
+    if start {
+        services.timer.start(None);
+    }
+    log::info!("Running headless, press Ctrl+C to stop");
+
+    wait_for_shutdown_signal().await?;
+
+    // Timers are left running in the persisted state so they resume on the next launch
+    log::info!("Shutting down");
+    Ok(())
+}
+
+pub async fn send_toast(
+    config_path: &str,
+    title: String,
+    content: String,
+    group: Option<String>,
+    channels: Vec<String>,
+    server_args: &ServerArgs,
+) -> Result<(), CliError> {
+    let config = Config::load_from_file(config_path)?;
+    let server = server_args.apply(&config.server);
+    let group = match group {
+        Some(id) => config
+            .find_group(&id)
+            .ok_or_else(|| format!("Unknown reminder group: {}", id))?,
+        None => config
+            .groups()
+            .first()
+            .ok_or("No reminder group configured")?,
+    };
+    let mut toast = ToastMessage::from_config(group, title, content);
+    if let Some(channel) = channels.iter().find(|c| !is_valid_channel_name(c)) {
+        return Err(format!("Invalid channel name: {}", channel).into());
+    }
+    if !channels.is_empty() {
+        toast.channels = channels;
+    }
+
+    let host = client_host(&server);
+    let mut url = format!("ws://{}:{}{}", host, server.http_port, WEBSOCKET_PATH);
+    if let Some(token) = &server.token {
+        url = format!("{}?{}={}", url, TOKEN_PARAM, urlencoding::encode(token));
+    }
+    let (mut ws_stream, _) = connect_async(url.as_str())
+        .await
+        .map_err(|e| format!("Failed to connect to {}:{}: {}", host, server.http_port, e))?;
+
+    let hello = ClientMessage::Hello {
+        protocol_version: PROTOCOL_VERSION,
+        role: ClientRole::Controller,
+        name: None,
+        viewport: None,
+        channel: None,
+    };
+    ws_stream.send(hello.to_message()?).await?;
+    ws_stream
+        .send(ClientMessage::Toast(Box::new(toast)).to_message()?)
+        .await?;
+    ws_stream.close(None).await?;
+
+    log::info!("Toast sent to {}:{}", host, server.http_port);
+    Ok(())
+}
+
+pub async fn switch_profile(
+    config_path: &str,
+    name: Option<String>,
+    server_args: &ServerArgs,
+) -> Result<(), CliError> {
+    let mut config = Config::load_from_file(config_path)?;
+    let Some(name) = name else {
+        for profile in &config.profiles {
+            let marker = if profile.name == config.active_profile_name() {
+                "*"
+            } else {
+                " "
+            };
+            println!("{} {}", marker, profile.name);
+        }
+        return Ok(());
+    };
+
+    if config.profile(&name).is_none() {
+        return Err(format!("Unknown profile: {}", name).into());
+    }
+
+    let server = server_args.apply(&config.server);
+    let host = client_host(&server);
+    match TcpStream::connect((host, server.http_port)).await {
+        Ok(stream) => {
+            let body = serde_json::json!({ "name": name });
+            api_request(stream, &server, Method::PUT, "/api/profile", body).await?;
+            log::info!("Switched the running instance to profile {}", name);
+        }
+        // Nothing is running, so the file is ours to change
+        Err(_) => {
+            config.active_profile = name.clone();
+            config.save_to_file(config_path)?;
+            log::info!("{} will start with profile {}", config_path, name);
+        }
+    }
+    Ok(())
+}
+
+pub fn export_profile(
+    config_path: &str,
+    path: &Path,
+    profile: Option<String>,
+) -> Result<(), CliError> {
+    let config = Config::load_from_file(config_path)?;
+    let name = profile.unwrap_or_else(|| config.active_profile_name().to_string());
+    bundle::export_profile(&config, &name, path, &AudioManager::new()?)
+}
+
+pub fn import_bundle(config_path: &str, path: &Path) -> Result<(), CliError> {
+    let config = Config::load_from_file(config_path)?;
+    let summary = bundle::import_bundle(path, &config, &AudioManager::new()?, |config| {
+        config.save_to_file(config_path)
+    })?;
+
+    if summary.renamed_profile {
+        log::info!(
+            "A profile with that name exists, imported as {}",
+            summary.profile
+        );
+    }
+    if summary.new_group_ids > 0 {
+        log::info!(
+            "{} group(s) got a new id because theirs was already in use",
+            summary.new_group_ids
+        );
+    }
+    Ok(())
+}
+
+/// Sends a JSON request to the HTTP API of a running instance.
+async fn api_request(
+    stream: TcpStream,
+    server: &ServerConfig,
+    method: Method,
+    path: &str,
+    body: serde_json::Value,
+) -> Result<(), CliError> {
+    let (mut sender, connection) =
+        hyper::client::conn::http1::handshake(TokioIo::new(stream)).await?;
+    tokio::spawn(connection);
+
+    let mut request = Request::builder()
+        .method(method)
+        .uri(path)
+        .header(
+            HOST,
+            format!("{}:{}", client_host(server), server.http_port),
+        )
+        .header(CONTENT_TYPE, "application/json");
+    if let Some(token) = &server.token {
+        request = request.header(AUTHORIZATION, format!("Bearer {}", token));
+    }
+
+    let response = sender
+        .send_request(request.body(Full::new(Bytes::from(body.to_string())))?)
+        .await?;
+    let status = response.status();
+    let body = response.into_body().collect().await?.to_bytes();
+
+    if !status.is_success() {
+        let error = serde_json::from_slice::<serde_json::Value>(&body)
+            .ok()
+            .and_then(|value| value["error"].as_str().map(str::to_string))
+            .unwrap_or_else(|| status.to_string());
+        return Err(error.into());
+    }
+    Ok(())
+}
+
+/// A server listening on every interface is also reachable on the loopback address.
+fn client_host(server: &ServerConfig) -> &str {
+    match server.host.as_str() {
+        "0.0.0.0" | "::" => "127.0.0.1",
+        host => host,
+    }
+}
+
+pub fn validate_config(config_path: &str) -> Result<(), CliError> {
+    let config = Config::load_from_file(config_path)
+        .map_err(|e| format!("Invalid configuration in {}: {}", config_path, e))?;
+    println!("{} is valid", config_path);
+    for warning in config.check().warnings() {
+        println!("warning: {}", warning);
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+async fn wait_for_shutdown_signal() -> std::io::Result<()> {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let mut terminate = signal(SignalKind::terminate())?;
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => result?,
+        _ = terminate.recv() => log::info!("Received SIGTERM"),
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+async fn wait_for_shutdown_signal() -> std::io::Result<()> {
+    tokio::signal::ctrl_c().await
+}
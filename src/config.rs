@@ -1,34 +1,205 @@
+use crate::config_migration::{self, SCHEMA_VERSION};
+use crate::markup::TextFormat;
 use serde::{Deserialize, Serialize};
+use std::fmt;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT_HTTP_PORT: u16 = 8080;
+pub const DEFAULT_WEBSOCKET_PORT: u16 = 7981;
+pub const MAX_VISIBLE_TOASTS: u32 = 10;
+pub const DEFAULT_PROFILE_NAME: &str = "默认";
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
-    pub toaster: ToasterConfig,
+    // Read by `config_migration` before deserializing, always the current version in memory
+    #[serde(skip_deserializing, default = "current_schema_version")]
+    pub schema_version: u32,
+    #[serde(default)]
+    pub active_profile: String, // name of the profile whose groups run; the first if empty
+    #[serde(default)]
+    pub app: AppConfig,
+    #[serde(default)]
+    pub server: ServerConfig,
+    #[serde(default)]
+    pub profiles: Vec<Profile>,
+}
+
+/// A named set of reminder groups, e.g. one per kind of stream. Only the groups of the active
+/// profile have timers.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Profile {
+    pub name: String,
+    #[serde(default)]
+    pub groups: Vec<ToasterConfig>, // independent reminder groups
+}
+
+/// Settings that apply to the whole application rather than a single group.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppConfig {
+    pub missed_toast_policy: MissedToastPolicy, // what to do with toasts due while closed
+    pub overlap_policy: OverlapPolicy, // what to do with a toast while others are on screen
+    pub max_visible_toasts: u32,       // toasts on screen at once with `OverlapPolicy::Stack`
+}
+
+/// Where the HTTP and WebSocket servers listen. Changes take effect on the next start.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct ServerConfig {
+    pub host: String, // "127.0.0.1" for this machine only, "0.0.0.0" for the whole network
+    pub http_port: u16,
+    pub websocket_port: Option<u16>, // extra WebSocket listener; `/ws` on the HTTP port always works
+    pub port_fallback: bool,         // use another free port when the configured one is taken
+    pub token: Option<String>,       // shared secret for the API, WebSocket and audio files
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub enum MissedToastPolicy {
+    /// Drop missed toasts and continue with the next scheduled one
+    #[default]
+    #[serde(rename = "skip")]
+    Skip,
+    /// Show a single toast right away for everything that was missed
+    #[serde(rename = "fire_once")]
+    FireOnce,
+}
+
+/// How a toast is handled when it would overlap toasts already on screen.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub enum OverlapPolicy {
+    /// Show up to `max_visible_toasts` at once, queue the rest
+    #[default]
+    #[serde(rename = "stack")]
+    Stack,
+    /// Show one toast at a time, in order
+    #[serde(rename = "queue")]
+    Queue,
+    /// Dismiss the toast on screen and show the new one
+    #[serde(rename = "replace")]
+    Replace,
+    /// Discard the new toast while another one is on screen
+    #[serde(rename = "drop")]
+    Drop,
 }
 
+/// Queued toasts are shown highest priority first; a higher priority also wins over
+/// `Replace` and `Drop` (see `ToastQueue`).
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    #[serde(rename = "low")]
+    Low,
+    #[default]
+    #[serde(rename = "normal")]
+    Normal,
+    #[serde(rename = "high")]
+    High,
+}
+
+/// A reminder group with its own contents, schedule and style. Missing fields take the
+/// values of a new group.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ToasterConfig {
-    pub titles: Vec<String>,
-    pub contents: Vec<String>,
+    pub id: String,
+    pub name: String,
+    #[serde(default)] // no sample reminders for a group that lists none
+    pub reminders: Vec<Reminder>,
     pub interval_time: u32, // minutes
     pub duration: u32,      // seconds
     pub color_1: String,    // hex color
     pub color_2: String,    // hex color
     pub text_color: String, // hex color for text
+    pub schedule: Schedule,
     pub content_switch_mode: ContentSwitchMode,
     pub toast_direction: ToastDirection, // animation direction for toast
+    pub text_format: TextFormat,         // plain text, the safe markup subset or Markdown
+    pub channels: Vec<String>,           // overlay channels to show on; empty means every overlay
+    pub priority: Priority,              // order in the toast queue
     pub enable_sound: bool,              // enable/disable sound notifications
     pub sound_file_id: Option<String>,   // UUID of the cached sound file
     pub sound_file_name: Option<String>, // original filename for display
 }
 
+/// A title and content shown together in one toast.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Reminder {
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub content: String,
+    #[serde(default = "default_weight")]
+    pub weight: u32, // relative weight for weighted selection
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum ContentSwitchMode {
     #[serde(rename = "random")]
     Random,
     #[serde(rename = "sequential")]
     Sequential,
+    #[serde(rename = "shuffle")]
+    Shuffle, // every reminder once per cycle, no back-to-back repeats
+    #[serde(rename = "weighted")]
+    Weighted, // random, using the reminder weights
+    #[serde(rename = "least_recent")]
+    LeastRecent, // the reminder shown longest ago
+}
+
+/// When toasts fire. `interval` uses `interval_time`; the weekday and time window filters
+/// apply to every mode.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Schedule {
+    pub mode: ScheduleMode,
+    pub minutes: Vec<u32>,            // minutes past the hour for "hourly"
+    pub times: Vec<String>,           // "HH:MM" times of day for "daily"
+    pub weekdays: Vec<Weekday>,       // empty means every day
+    pub window_start: Option<String>, // "HH:MM", only fire after this time
+    pub window_end: Option<String>,   // "HH:MM", only fire before this time
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub enum ScheduleMode {
+    #[default]
+    #[serde(rename = "interval")]
+    Interval,
+    #[serde(rename = "hourly")]
+    Hourly,
+    #[serde(rename = "daily")]
+    Daily,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum Weekday {
+    #[serde(rename = "mon")]
+    Mon,
+    #[serde(rename = "tue")]
+    Tue,
+    #[serde(rename = "wed")]
+    Wed,
+    #[serde(rename = "thu")]
+    Thu,
+    #[serde(rename = "fri")]
+    Fri,
+    #[serde(rename = "sat")]
+    Sat,
+    #[serde(rename = "sun")]
+    Sun,
+}
+
+impl Weekday {
+    pub const ALL: [Weekday; 7] = [
+        Weekday::Mon,
+        Weekday::Tue,
+        Weekday::Wed,
+        Weekday::Thu,
+        Weekday::Fri,
+        Weekday::Sat,
+        Weekday::Sun,
+    ];
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -43,100 +214,620 @@ pub enum ToastDirection {
     Right,
 }
 
-impl Default for Config {
+impl ToastDirection {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ToastDirection::Top => "top",
+            ToastDirection::Bottom => "bottom",
+            ToastDirection::Left => "left",
+            ToastDirection::Right => "right",
+        }
+    }
+}
+
+impl Default for ToasterConfig {
     fn default() -> Self {
         Self {
-            toaster: ToasterConfig {
-                titles: vec!["提醒标题1".to_string(), "提醒标题2".to_string()],
-                contents: vec!["提醒内容1".to_string(), "提醒内容2".to_string()],
-                interval_time: 5,
-                duration: 5, // 5 seconds default
-                color_1: "#FF6B6B".to_string(),
-                color_2: "#4ECDC4".to_string(),
-                text_color: "#FFFFFF".to_string(), // white text default
-                content_switch_mode: ContentSwitchMode::Random,
-                toast_direction: ToastDirection::Top, // default from top
-                enable_sound: false,                  // disabled by default
-                sound_file_id: None,                  // no custom sound file by default
-                sound_file_name: None,                // no filename by default
-            },
+            id: new_group_id(),
+            name: default_group_name(),
+            reminders: vec![
+                Reminder::new("提醒标题1", "提醒内容1"),
+                Reminder::new("提醒标题2", "提醒内容2"),
+            ],
+            interval_time: 5,
+            schedule: Schedule::default(),
+            duration: 5, // 5 seconds default
+            color_1: "#FF6B6B".to_string(),
+            color_2: "#4ECDC4".to_string(),
+            text_color: "#FFFFFF".to_string(), // white text default
+            content_switch_mode: ContentSwitchMode::Random,
+            toast_direction: ToastDirection::Top, // default from top
+            text_format: TextFormat::Plain,
+            channels: Vec::new(), // shown on every overlay
+            priority: Priority::Normal,
+            enable_sound: false,   // disabled by default
+            sound_file_id: None,   // no custom sound file by default
+            sound_file_name: None, // no filename by default
         }
     }
 }
 
-impl Config {
-    pub fn validate(&self) -> Result<(), String> {
-        // Validate titles and contents are not empty
-        if self.toaster.titles.is_empty() {
-            return Err("At least one title is required".to_string());
+impl Default for AppConfig {
+    fn default() -> Self {
+        Self {
+            missed_toast_policy: MissedToastPolicy::default(),
+            overlap_policy: OverlapPolicy::default(),
+            max_visible_toasts: 3,
         }
+    }
+}
 
-        if self.toaster.contents.is_empty() {
-            return Err("At least one content is required".to_string());
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            host: "127.0.0.1".to_string(),
+            http_port: DEFAULT_HTTP_PORT,
+            websocket_port: None,
+            port_fallback: true,
+            token: None,
         }
+    }
+}
 
-        // Validate interval time is reasonable (1-1440 minutes = 1 day)
-        if self.toaster.interval_time == 0 || self.toaster.interval_time > 1440 {
-            return Err("Interval time must be between 1 and 1440 minutes".to_string());
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            active_profile: DEFAULT_PROFILE_NAME.to_string(),
+            app: AppConfig::default(),
+            server: ServerConfig::default(),
+            profiles: vec![Profile::new(DEFAULT_PROFILE_NAME)],
         }
+    }
+}
 
-        // Validate duration is reasonable (1-60 seconds)
-        if self.toaster.duration == 0 || self.toaster.duration > 60 {
-            return Err("Toast duration must be between 1 and 60 seconds".to_string());
+impl Profile {
+    /// A profile with a single default group.
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            groups: vec![ToasterConfig::default()],
         }
+    }
 
-        // Validate hex colors
-        if !is_valid_hex_color(&self.toaster.color_1) {
-            return Err("Invalid hex color format for color_1".to_string());
+    /// A copy under another name whose groups get new ids, so their timers stay separate.
+    pub fn duplicate(&self, name: &str) -> Self {
+        let mut profile = self.clone();
+        profile.name = name.to_string();
+        for group in &mut profile.groups {
+            group.id = new_group_id();
         }
+        profile
+    }
+}
 
-        if !is_valid_hex_color(&self.toaster.color_2) {
-            return Err("Invalid hex color format for color_2".to_string());
+impl Reminder {
+    pub fn new(title: &str, content: &str) -> Self {
+        Self {
+            title: title.to_string(),
+            content: content.to_string(),
+            weight: default_weight(),
+            enabled: default_enabled(),
         }
+    }
+}
+
+impl ToasterConfig {
+    pub fn enabled_reminders(&self) -> Vec<&Reminder> {
+        self.reminders.iter().filter(|r| r.enabled).collect()
+    }
+}
+
+impl Config {
+    pub fn profile(&self, name: &str) -> Option<&Profile> {
+        self.profiles.iter().find(|profile| profile.name == name)
+    }
+
+    pub fn active_profile_index(&self) -> usize {
+        self.profiles
+            .iter()
+            .position(|profile| profile.name == self.active_profile)
+            .unwrap_or(0)
+    }
 
-        if !is_valid_hex_color(&self.toaster.text_color) {
-            return Err("Invalid hex color format for text_color".to_string());
+    /// Name of the profile whose groups run.
+    pub fn active_profile_name(&self) -> &str {
+        self.profiles
+            .get(self.active_profile_index())
+            .map_or("", |profile| profile.name.as_str())
+    }
+
+    /// Groups of the active profile.
+    pub fn groups(&self) -> &[ToasterConfig] {
+        self.profiles
+            .get(self.active_profile_index())
+            .map_or(&[], |profile| &profile.groups)
+    }
+
+    pub fn groups_mut(&mut self) -> &mut Vec<ToasterConfig> {
+        if self.profiles.is_empty() {
+            self.profiles.push(Profile::new(DEFAULT_PROFILE_NAME));
         }
+        let index = self.active_profile_index();
+        &mut self.profiles[index].groups
+    }
 
-        Ok(())
+    /// Groups of every profile.
+    pub fn all_groups(&self) -> impl Iterator<Item = &ToasterConfig> {
+        self.profiles.iter().flat_map(|profile| &profile.groups)
+    }
+
+    /// Whether saving one of the configurations would produce the same file as the other.
+    pub fn same_as(&self, other: &Config) -> bool {
+        toml::to_string(self).ok() == toml::to_string(other).ok()
+    }
+
+    /// Finds a group of the active profile by id.
+    pub fn group(&self, id: &str) -> Option<&ToasterConfig> {
+        self.groups().iter().find(|group| group.id == id)
+    }
+
+    /// Finds a group of the active profile by id or, failing that, by name.
+    pub fn find_group(&self, id_or_name: &str) -> Option<&ToasterConfig> {
+        self.group(id_or_name)
+            .or_else(|| self.groups().iter().find(|group| group.name == id_or_name))
     }
 
     pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn std::error::Error>> {
         let content = fs::read_to_string(path)?;
-        let config: Config = toml::from_str(&content)?;
-        config.validate()?;
+        Ok(Self::parse(&content)?)
+    }
+
+    /// Parses the content of a configuration file in any schema version and validates it.
+    pub fn parse(content: &str) -> Result<Self, ConfigLoadError> {
+        let mut file: toml::Table =
+            toml::from_str(content).map_err(|e| ConfigLoadError::from_toml(content, &e))?;
+        let version = config_migration::migrate(&mut file).map_err(ConfigLoadError::new)?;
+        let added_ids = config_migration::assign_group_ids(&mut file);
+
+        let mut config: Config = if version == SCHEMA_VERSION {
+            // Deserializing the text itself keeps line numbers in the errors
+            toml::from_str(content).map_err(|e| ConfigLoadError::from_toml(content, &e))?
+        } else {
+            log::info!(
+                "Migrating configuration from schema version {} to {}",
+                version,
+                SCHEMA_VERSION
+            );
+            toml::Value::Table(file)
+                .try_into()
+                .map_err(|e: toml::de::Error| ConfigLoadError::new(e.message().to_string()))?
+        };
+
+        // The text does not have the ids added to the table
+        for (profile, group, id) in added_ids {
+            if let Some(group) = config
+                .profiles
+                .get_mut(profile)
+                .and_then(|profile| profile.groups.get_mut(group))
+            {
+                group.id = id;
+            }
+        }
+
+        config
+            .validate()
+            .map_err(|e| ConfigLoadError::new(e.to_string()))?;
         Ok(config)
     }
 
     pub fn save_to_file<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn std::error::Error>> {
         self.validate()?;
+        let path = path.as_ref();
         let toml_string = toml::to_string_pretty(self)?;
-        fs::write(path, toml_string)?;
+
+        // The file is watched for changes, so never let a half-written file be read
+        let tmp_path = path.with_extension("toml.tmp");
+        fs::write(&tmp_path, toml_string)?;
+        fs::rename(&tmp_path, path)?;
         Ok(())
     }
 
-    pub fn load_or_create_default<P: AsRef<Path>>(path: P) -> Self {
-        match Self::load_from_file(&path) {
+    /// Loads the configuration, creating the file with defaults when there is none. A file that
+    /// cannot be used is copied to a timestamped backup and left as it is; the defaults are
+    /// returned together with the error.
+    pub fn load_or_default<P: AsRef<Path>>(path: P) -> (Self, Option<ConfigLoadError>) {
+        let path = path.as_ref();
+
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                log::info!("No configuration at {}, creating one", path.display());
+                let config = Self::default();
+                if let Err(e) = config.save_to_file(path) {
+                    log::error!("Failed to save default configuration: {}", e);
+                }
+                return (config, None);
+            }
+            Err(e) => {
+                let error = ConfigLoadError::new(e.to_string());
+                log::error!("Failed to read {}: {}", path.display(), error);
+                return (Self::default(), Some(error));
+            }
+        };
+
+        match Self::parse(&content) {
             Ok(config) => {
                 log::info!("Configuration loaded from file");
-                config
+                (config, None)
             }
-            Err(e) => {
-                log::warn!("Failed to load config: {}. Using default configuration.", e);
-                let default_config = Self::default();
-                if let Err(save_err) = default_config.save_to_file(&path) {
-                    log::error!("Failed to save default configuration: {}", save_err);
-                }
-                default_config
+            Err(mut error) => {
+                error.backup_path = backup_file(path);
+                log::error!("Invalid configuration in {}: {}", path.display(), error);
+                (Self::default(), Some(error))
             }
         }
     }
 }
 
-fn is_valid_hex_color(color: &str) -> bool {
+/// Why a configuration file could not be used.
+#[derive(Debug, Clone)]
+pub struct ConfigLoadError {
+    pub message: String,
+    pub line: Option<usize>, // 1-based position of a TOML syntax error
+    pub column: Option<usize>,
+    pub backup_path: Option<PathBuf>, // copy of the broken file
+}
+
+impl ConfigLoadError {
+    fn new(message: String) -> Self {
+        Self {
+            message,
+            line: None,
+            column: None,
+            backup_path: None,
+        }
+    }
+
+    fn from_toml(content: &str, error: &toml::de::Error) -> Self {
+        let (line, column) = error
+            .span()
+            .map(|span| line_and_column(content, span.start))
+            .unzip();
+        Self {
+            line,
+            column,
+            ..Self::new(error.message().to_string())
+        }
+    }
+}
+
+impl fmt::Display for ConfigLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let (Some(line), Some(column)) = (self.line, self.column) {
+            write!(f, "line {}, column {}: ", line, column)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ConfigLoadError {}
+
+/// 1-based line and column (in characters) of a byte offset.
+fn line_and_column(content: &str, offset: usize) -> (usize, usize) {
+    let before = &content[..offset.min(content.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before
+        .rsplit('\n')
+        .next()
+        .map_or(0, |line| line.chars().count())
+        + 1;
+    (line, column)
+}
+
+/// Copies a broken configuration next to it as `config.toml.<timestamp>.bak`.
+fn backup_file(path: &Path) -> Option<PathBuf> {
+    let file_name = path.file_name()?.to_string_lossy();
+    let timestamp = chrono::Local::now().format("%Y%m%d-%H%M%S");
+    let backup_path = path.with_file_name(format!("{}.{}.bak", file_name, timestamp));
+
+    match fs::copy(path, &backup_path) {
+        Ok(_) => {
+            log::info!("Backed up the configuration to {}", backup_path.display());
+            Some(backup_path)
+        }
+        Err(e) => {
+            log::error!("Failed to back up {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+pub fn new_group_id() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+fn current_schema_version() -> u32 {
+    SCHEMA_VERSION
+}
+
+fn default_group_name() -> String {
+    "默认".to_string()
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// Channel names end up in browser source URLs, so keep them to URL-safe characters.
+pub fn is_valid_channel_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+}
+
+pub fn is_valid_hex_color(color: &str) -> bool {
     if !color.starts_with('#') || color.len() != 7 {
         return false;
     }
 
     color[1..].chars().all(|c| c.is_ascii_hexdigit())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // One file for every layout `config_migration` knows about
+    const FIXTURES: [(&str, &str); 6] = [
+        (
+            "v1_readme",
+            include_str!("../tests/fixtures/config/v1_readme.toml"),
+        ),
+        (
+            "v1_saved",
+            include_str!("../tests/fixtures/config/v1_saved.toml"),
+        ),
+        (
+            "v2_groups",
+            include_str!("../tests/fixtures/config/v2_groups.toml"),
+        ),
+        (
+            "v3_unversioned",
+            include_str!("../tests/fixtures/config/v3_unversioned.toml"),
+        ),
+        ("v3", include_str!("../tests/fixtures/config/v3.toml")),
+        ("v4", include_str!("../tests/fixtures/config/v4.toml")),
+    ];
+
+    fn load(name: &str) -> Config {
+        let (_, content) = FIXTURES.iter().find(|(n, _)| *n == name).unwrap();
+        Config::parse(content).unwrap_or_else(|e| panic!("{}: {}", name, e))
+    }
+
+    fn pairs(group: &ToasterConfig) -> Vec<(&str, &str)> {
+        group
+            .reminders
+            .iter()
+            .map(|r| (r.title.as_str(), r.content.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn readme_example_fills_fields_it_lacks_with_defaults() {
+        let config = load("v1_readme");
+
+        assert_eq!(config.schema_version, SCHEMA_VERSION);
+        assert_eq!(config.groups().len(), 1);
+        let group = &config.groups()[0];
+        assert_eq!(
+            pairs(group),
+            [
+                ("bbb", "im content"),
+                ("114514", "hoho"),
+                ("alright", "what can i say?")
+            ]
+        );
+        assert_eq!(group.interval_time, 30);
+        assert_eq!(group.toast_direction, ToastDirection::Top);
+        assert!(!group.enable_sound);
+        assert_eq!(group.schedule, Schedule::default());
+        assert_eq!(config.server, ServerConfig::default());
+    }
+
+    #[test]
+    fn single_toaster_becomes_the_first_group() {
+        let config = load("v1_saved");
+
+        assert_eq!(config.groups().len(), 1);
+        let group = &config.groups()[0];
+        // The shorter title list wraps around
+        assert_eq!(
+            pairs(group),
+            [
+                ("喝水", "该喝水了"),
+                ("休息", "站起来活动一下"),
+                ("喝水", "看看远处")
+            ]
+        );
+        assert_eq!(group.content_switch_mode, ContentSwitchMode::Sequential);
+        assert_eq!(group.toast_direction, ToastDirection::Left);
+        assert!(group.enable_sound);
+        assert_eq!(group.sound_file_name.as_deref(), Some("ding.mp3"));
+    }
+
+    #[test]
+    fn groups_with_title_and_content_lists_get_reminders() {
+        let config = load("v2_groups");
+
+        assert_eq!(config.groups().len(), 2);
+        assert_eq!(
+            config.groups()[0].id,
+            "6d5f1a3c-8e2b-4f7a-9c1d-0b3e5a7f9c21"
+        );
+        assert_eq!(
+            pairs(&config.groups()[0]),
+            [
+                ("Drink water", "Stay hydrated"),
+                ("Drink water", "Take a sip")
+            ]
+        );
+        assert_eq!(config.groups()[0].schedule.mode, ScheduleMode::Hourly);
+        assert_eq!(config.groups()[0].schedule.minutes, [0, 30]);
+        assert_eq!(
+            pairs(&config.groups()[1]),
+            [("Chat", "Say hi to new viewers")]
+        );
+        assert!(config.groups().iter().all(|g| g.reminders[0].weight == 1));
+    }
+
+    #[test]
+    fn unversioned_reminders_are_kept_as_written() {
+        let config = load("v3_unversioned");
+
+        assert_eq!(config.app.missed_toast_policy, MissedToastPolicy::FireOnce);
+        assert_eq!(config.app.overlap_policy, OverlapPolicy::Queue);
+        assert_eq!(config.app.max_visible_toasts, 3);
+        assert_eq!(config.server.http_port, 9090);
+        assert_eq!(config.server.token.as_deref(), Some("secret"));
+
+        let group = &config.groups()[0];
+        assert_eq!(group.text_format, TextFormat::Markdown);
+        assert_eq!(group.channels, ["main"]);
+        assert_eq!(group.priority, Priority::High);
+        assert_eq!(group.reminders.len(), 2);
+        assert_eq!(group.reminders[0].weight, 3);
+        assert!(!group.reminders[1].enabled);
+    }
+
+    #[test]
+    fn groups_become_the_default_profile() {
+        let config = load("v3");
+
+        assert_eq!(config.profiles.len(), 1);
+        assert_eq!(config.active_profile, DEFAULT_PROFILE_NAME);
+        assert_eq!(config.profiles[0].name, DEFAULT_PROFILE_NAME);
+
+        assert_eq!(config.app.max_visible_toasts, 2);
+        assert_eq!(config.server.websocket_port, Some(7981));
+        let group = &config.groups()[0];
+        assert_eq!(group.content_switch_mode, ContentSwitchMode::LeastRecent);
+        assert_eq!(group.schedule.times, ["20:00", "21:30"]);
+        assert_eq!(group.priority, Priority::Low);
+        assert_eq!(pairs(group), [("提醒标题1", "<b>提醒内容1</b>")]);
+    }
+
+    #[test]
+    fn current_version_loads_unchanged() {
+        let config = load("v4");
+
+        assert_eq!(config.app.overlap_policy, OverlapPolicy::Replace);
+        assert_eq!(config.profiles.len(), 2);
+        assert_eq!(config.profiles[0].groups.len(), 1);
+        assert_eq!(config.active_profile_name(), "Gaming");
+
+        // Only the active profile's groups are used
+        let names: Vec<&str> = config.groups().iter().map(|g| g.name.as_str()).collect();
+        assert_eq!(names, ["Drink water", "Stretch"]);
+        assert!(config.find_group("Chat").is_none());
+        assert_eq!(
+            config.groups()[0].content_switch_mode,
+            ContentSwitchMode::Shuffle
+        );
+    }
+
+    #[test]
+    fn unknown_active_profile_is_rejected() {
+        let mut config = load("v4");
+        config.active_profile = "Podcast".to_string();
+        assert!(config.validate().is_err());
+
+        // An empty name falls back to the first profile
+        config.active_profile.clear();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.active_profile_name(), "Just chatting");
+    }
+
+    #[test]
+    fn group_ids_are_unique_across_profiles() {
+        let mut config = load("v4");
+        let copy = config.profiles[0].clone();
+        config.profiles.push(Profile {
+            name: "Copy".to_string(),
+            ..copy.clone()
+        });
+        assert!(config.validate().is_err());
+
+        config.profiles.pop();
+        config.profiles.push(copy.duplicate("Copy"));
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn every_fixture_saves_in_the_current_layout() {
+        for (name, content) in FIXTURES {
+            let config = Config::parse(content).unwrap();
+            let saved = toml::to_string_pretty(&config).unwrap();
+
+            assert!(
+                saved.starts_with(&format!("schema_version = {}\n", SCHEMA_VERSION)),
+                "{}",
+                name
+            );
+            assert!(!saved.contains("titles") && !saved.contains("[toaster]"));
+            assert!(!saved.contains("\n[[groups]]"), "{}", name);
+            assert!(Config::parse(&saved).unwrap().same_as(&config), "{}", name);
+        }
+    }
+
+    #[test]
+    fn missing_group_fields_take_defaults() {
+        let config = Config::parse("[[groups]]\n[[groups.reminders]]\ntitle = \"Hi\"\n").unwrap();
+
+        let group = &config.groups()[0];
+        let defaults = ToasterConfig::default();
+        assert_eq!(group.interval_time, defaults.interval_time);
+        assert_eq!(group.color_1, defaults.color_1);
+        assert_eq!(group.reminders, [Reminder::new("Hi", "")]);
+    }
+
+    #[test]
+    fn groups_without_id_get_the_same_one_on_every_load() {
+        let ids = |config: &Config| -> Vec<String> {
+            config.all_groups().map(|group| group.id.clone()).collect()
+        };
+
+        for name in ["v1_readme", "v1_saved"] {
+            assert_eq!(ids(&load(name)), ["group-1-1"], "{}", name);
+        }
+
+        // Written by hand in the current layout, next to a group that has an id
+        let content = "schema_version = 4\n\
+            [[profiles]]\nname = \"A\"\n\
+            [[profiles.groups]]\n[[profiles.groups.reminders]]\ntitle = \"Hi\"\n\
+            [[profiles]]\nname = \"B\"\n\
+            [[profiles.groups]]\nid = \"group-1-1\"\n[[profiles.groups.reminders]]\ntitle = \"Hi\"\n\
+            [[profiles.groups]]\n[[profiles.groups.reminders]]\ntitle = \"Hi\"\n";
+        let config = Config::parse(content).unwrap();
+        assert_eq!(ids(&config), ["group-1-1-2", "group-1-1", "group-2-2"]);
+        assert_eq!(ids(&Config::parse(content).unwrap()), ids(&config));
+    }
+
+    #[test]
+    fn newer_schema_version_is_rejected() {
+        let error = Config::parse("schema_version = 99\n").unwrap_err();
+        assert!(error.message.contains("newer"), "{}", error);
+    }
+
+    #[test]
+    fn errors_in_current_files_have_a_position() {
+        let content = "schema_version = 4\n[[profiles]]\nname = \"A\"\n[[profiles.groups]]\nduration = \"x\"\n";
+        let error = Config::parse(content).unwrap_err();
+        assert_eq!((error.line, error.column), (Some(5), Some(12)));
+    }
+}
@@ -0,0 +1,217 @@
+use crate::config::DEFAULT_PROFILE_NAME;
+use toml::{Table, Value};
+
+/// Upgrades a file from the schema version at its index + 1 to the next one.
+type Migration = fn(&mut Table) -> Result<(), String>;
+
+const MIGRATIONS: [Migration; 3] = [
+    single_toaster_to_groups,
+    pair_titles_and_contents,
+    groups_to_default_profile,
+];
+
+/// Layout written by this version, stored as `schema_version` at the top of `config.toml`.
+///
+/// 1. a single `[toaster]` table with parallel `titles`/`contents` lists
+/// 2. `[[groups]]`, each with its own `titles`/`contents`
+/// 3. `[[groups.reminders]]` pairing a title with its content
+/// 4. `[[profiles]]`, each with its own `[[profiles.groups]]`, and `active_profile`
+pub const SCHEMA_VERSION: u32 = MIGRATIONS.len() as u32 + 1;
+
+/// The first version with `[[profiles]]`, so the oldest a profile saved on its own can have.
+const PROFILES_VERSION: u32 = 4;
+
+/// Brings a parsed file up to [`SCHEMA_VERSION`] and returns the version it was written in.
+/// Fields added within a version are not migrated; they have serde defaults instead.
+pub fn migrate(file: &mut Table) -> Result<u32, String> {
+    let version = schema_version(file)?;
+    if version > SCHEMA_VERSION {
+        return Err(format!(
+            "Schema version {} was written by a newer OBS Reminder, this one understands up to {}",
+            version, SCHEMA_VERSION
+        ));
+    }
+
+    for migration in &MIGRATIONS[version as usize - 1..] {
+        migration(file)?;
+    }
+    file.insert(
+        "schema_version".to_string(),
+        Value::Integer(SCHEMA_VERSION.into()),
+    );
+    Ok(version)
+}
+
+/// Brings a profile saved on its own in schema `version`, as in a bundle, up to
+/// [`SCHEMA_VERSION`] by migrating it as the only profile of a file.
+pub fn migrate_profile(profile: Table, version: u32) -> Result<Table, String> {
+    if version < PROFILES_VERSION {
+        return Err(format!(
+            "Schema version {} has no profiles, expected at least {}",
+            version, PROFILES_VERSION
+        ));
+    }
+
+    let mut file = Table::new();
+    file.insert("schema_version".to_string(), Value::Integer(version.into()));
+    file.insert(
+        "profiles".to_string(),
+        Value::Array(vec![Value::Table(profile)]),
+    );
+    migrate(&mut file)?;
+
+    match file.remove("profiles") {
+        Some(Value::Array(mut profiles)) if profiles.len() == 1 => match profiles.remove(0) {
+            Value::Table(profile) => Ok(profile),
+            _ => Err("A profile must be a table".to_string()),
+        },
+        _ => Err("The migrated file has no single profile".to_string()),
+    }
+}
+
+fn schema_version(file: &Table) -> Result<u32, String> {
+    match file.get("schema_version") {
+        Some(Value::Integer(version)) => u32::try_from(*version)
+            .ok()
+            .filter(|&version| version >= 1)
+            .ok_or_else(|| format!("Invalid schema_version {}", version)),
+        Some(_) => Err("schema_version must be a number".to_string()),
+        // Files from before versioning: only the first layout lacks [[groups]]
+        None if file.contains_key("toaster") => Ok(1),
+        None => Ok(2),
+    }
+}
+
+/// 1 → 2: the `[toaster]` table becomes the first group.
+fn single_toaster_to_groups(file: &mut Table) -> Result<(), String> {
+    // Held the app version in the earliest files and was never read
+    if let Some(Value::Table(app)) = file.get_mut("app") {
+        app.remove("version");
+    }
+
+    let Some(toaster) = file.remove("toaster") else {
+        return Ok(());
+    };
+    match file
+        .entry("groups")
+        .or_insert_with(|| Value::Array(Vec::new()))
+    {
+        Value::Array(groups) => groups.insert(0, toaster),
+        _ => return Err("groups must be a list of [[groups]] tables".to_string()),
+    }
+    Ok(())
+}
+
+/// 2 → 3: pairs each group's `titles` and `contents` into reminders, wrapping the shorter list
+/// the same way the old sequential mode did.
+fn pair_titles_and_contents(file: &mut Table) -> Result<(), String> {
+    let Some(Value::Array(groups)) = file.get_mut("groups") else {
+        return Ok(());
+    };
+
+    for group in groups {
+        // Anything else is reported when the file is deserialized
+        let Value::Table(group) = group else {
+            continue;
+        };
+
+        let titles = take_list(group, "titles");
+        let contents = take_list(group, "contents");
+        if group.contains_key("reminders") || titles.is_empty() || contents.is_empty() {
+            continue;
+        }
+
+        let reminders = (0..titles.len().max(contents.len()))
+            .map(|i| {
+                let mut reminder = Table::new();
+                reminder.insert("title".to_string(), titles[i % titles.len()].clone());
+                reminder.insert("content".to_string(), contents[i % contents.len()].clone());
+                Value::Table(reminder)
+            })
+            .collect();
+        group.insert("reminders".to_string(), Value::Array(reminders));
+    }
+    Ok(())
+}
+
+/// 3 → 4: the groups become the only profile.
+fn groups_to_default_profile(file: &mut Table) -> Result<(), String> {
+    let groups = file
+        .remove("groups")
+        .unwrap_or_else(|| Value::Array(Vec::new()));
+
+    let mut profile = Table::new();
+    profile.insert(
+        "name".to_string(),
+        Value::String(DEFAULT_PROFILE_NAME.to_string()),
+    );
+    profile.insert("groups".to_string(), groups);
+
+    file.insert(
+        "active_profile".to_string(),
+        Value::String(DEFAULT_PROFILE_NAME.to_string()),
+    );
+    file.insert(
+        "profiles".to_string(),
+        Value::Array(vec![Value::Table(profile)]),
+    );
+    Ok(())
+}
+
+/// Gives groups without an `id`, such as those of older files or written by hand, one derived
+/// from their position. A file that is loaded but never saved then keeps the same ids, and with
+/// them its timer state, on every load. Returns the profile index, group index and id of every
+/// id that was added.
+pub fn assign_group_ids(file: &mut Table) -> Vec<(usize, usize, String)> {
+    let Some(Value::Array(profiles)) = file.get_mut("profiles") else {
+        return Vec::new();
+    };
+    let mut taken: Vec<String> = Vec::new();
+    for profile in profiles.iter_mut() {
+        for group in profile_groups(profile) {
+            if let Some(Value::String(id)) = group.get("id") {
+                taken.push(id.clone());
+            }
+        }
+    }
+
+    let mut assigned = Vec::new();
+    for (i, profile) in profiles.iter_mut().enumerate() {
+        for (j, group) in profile_groups(profile).enumerate() {
+            // Anything else is reported when the file is deserialized
+            let Value::Table(group) = group else {
+                continue;
+            };
+            if group.contains_key("id") {
+                continue;
+            }
+
+            let base = format!("group-{}-{}", i + 1, j + 1);
+            let mut id = base.clone();
+            for n in 2.. {
+                if !taken.contains(&id) {
+                    break;
+                }
+                id = format!("{}-{}", base, n);
+            }
+            taken.push(id.clone());
+            group.insert("id".to_string(), Value::String(id.clone()));
+            assigned.push((i, j, id));
+        }
+    }
+    assigned
+}
+
+fn profile_groups(profile: &mut Value) -> impl Iterator<Item = &mut Value> {
+    match profile.get_mut("groups") {
+        Some(Value::Array(groups)) => groups.iter_mut(),
+        _ => [].iter_mut(),
+    }
+}
+
+fn take_list(table: &mut Table, key: &str) -> Vec<Value> {
+    match table.remove(key) {
+        Some(Value::Array(values)) => values,
+        _ => Vec::new(),
+    }
+}
@@ -1,13 +1,17 @@
-use crate::audio_manager::AudioManager;
-use http_body_util::{BodyExt, Full, combinators::BoxBody};
+use crate::api::handle_api_request;
+use crate::audio_manager::{AudioManager, is_file_id};
+use crate::services::Services;
+use http_body_util::{BodyExt, Empty, Full, combinators::BoxBody};
 use hyper::server::conn::http1;
 use hyper::service::service_fn;
-use hyper::{Request, Response, StatusCode, body::Bytes};
+use hyper::{Request, Response, StatusCode, body::Bytes, header};
 use hyper_util::rt::TokioIo;
 use rust_embed::RustEmbed;
 use std::convert::Infallible;
-use std::net::SocketAddr;
 use tokio::net::TcpListener;
+use tokio_tungstenite::WebSocketStream;
+use tokio_tungstenite::tungstenite::handshake::derive_accept_key;
+use tokio_tungstenite::tungstenite::protocol::Role;
 
 #[derive(RustEmbed)]
 #[folder = "./browser/build/"]
@@ -15,29 +19,32 @@ struct Assets;
 
 type BoxError = Box<dyn std::error::Error + Send + Sync>;
 
+/// Path on the HTTP server that accepts WebSocket upgrades.
+pub const WEBSOCKET_PATH: &str = "/ws";
+
 #[derive(Clone)]
 pub struct HttpServer {
-    port: u16,
+    services: Services,
 }
 
 impl HttpServer {
-    pub fn new(port: u16) -> Self {
-        Self { port }
+    pub fn new(services: Services) -> Self {
+        Self { services }
     }
 
-    pub async fn start(&self) -> Result<(), BoxError> {
-        let addr = SocketAddr::from(([127, 0, 0, 1], self.port));
-        let listener = TcpListener::bind(addr).await?;
-
-        log::info!("HTTP server listening on http://{}", addr);
+    pub async fn start(&self, listener: TcpListener) -> Result<(), BoxError> {
+        log::info!("HTTP server listening on http://{}", listener.local_addr()?);
 
         loop {
             let (stream, _) = listener.accept().await?;
             let io = TokioIo::new(stream);
+            let services = self.services.clone();
 
             tokio::task::spawn(async move {
+                let service = service_fn(move |req| handle_request(req, services.clone()));
                 if let Err(err) = http1::Builder::new()
-                    .serve_connection(io, service_fn(handle_request))
+                    .serve_connection(io, service)
+                    .with_upgrades()
                     .await
                 {
                     log::error!("Error serving connection: {:?}", err);
@@ -49,9 +56,45 @@ impl HttpServer {
 
 async fn handle_request(
     req: Request<hyper::body::Incoming>,
+    services: Services,
 ) -> Result<Response<BoxBody<Bytes, Infallible>>, Infallible> {
     let path = req.uri().path();
 
+    // Everything but the static overlay page needs the token, if one is configured
+    if (path == WEBSOCKET_PATH || path.starts_with("/audio/"))
+        && !services.is_authorized(req.headers(), req.uri().query())
+    {
+        let body = Full::new(Bytes::from("Unauthorized"))
+            .map_err(|never| match never {})
+            .boxed();
+
+        return Ok(Response::builder()
+            .status(StatusCode::UNAUTHORIZED)
+            .body(body)
+            .unwrap());
+    }
+
+    // Overlays connect to the WebSocket on the same origin
+    if path == WEBSOCKET_PATH && !services.is_own_origin(req.headers()) {
+        log::warn!("Rejected WebSocket connection from another site");
+        let body = Full::new(Bytes::from("Forbidden"))
+            .map_err(|never| match never {})
+            .boxed();
+
+        return Ok(Response::builder()
+            .status(StatusCode::FORBIDDEN)
+            .body(body)
+            .unwrap());
+    }
+    if path == WEBSOCKET_PATH {
+        return Ok(handle_websocket_upgrade(req, services));
+    }
+
+    // Handle JSON API requests
+    if path.starts_with("/api/") {
+        return Ok(handle_api_request(req, services).await);
+    }
+
     // Handle audio file requests by UUID
     if path.starts_with("/audio/") {
         return handle_audio_request(path).await;
@@ -107,12 +150,69 @@ async fn handle_request(
     }
 }
 
+/// Completes the WebSocket handshake and hands the upgraded connection to the WebSocket server.
+fn handle_websocket_upgrade(
+    mut req: Request<hyper::body::Incoming>,
+    services: Services,
+) -> Response<BoxBody<Bytes, Infallible>> {
+    let is_upgrade = req
+        .headers()
+        .get(header::UPGRADE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.eq_ignore_ascii_case("websocket"));
+    let key = req.headers().get(header::SEC_WEBSOCKET_KEY);
+
+    let (true, Some(key)) = (is_upgrade, key) else {
+        let body = Full::new(Bytes::from("Expected a WebSocket upgrade"))
+            .map_err(|never| match never {})
+            .boxed();
+
+        return Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(body)
+            .unwrap();
+    };
+    let accept_key = derive_accept_key(key.as_bytes());
+
+    let on_upgrade = hyper::upgrade::on(&mut req);
+    tokio::spawn(async move {
+        match on_upgrade.await {
+            Ok(upgraded) => {
+                let ws_stream =
+                    WebSocketStream::from_raw_socket(TokioIo::new(upgraded), Role::Server, None)
+                        .await;
+                services.websocket_server.serve_upgraded(ws_stream);
+            }
+            Err(e) => log::error!("WebSocket upgrade failed: {}", e),
+        }
+    });
+
+    Response::builder()
+        .status(StatusCode::SWITCHING_PROTOCOLS)
+        .header(header::CONNECTION, "Upgrade")
+        .header(header::UPGRADE, "websocket")
+        .header(header::SEC_WEBSOCKET_ACCEPT, accept_key)
+        .body(Empty::new().map_err(|never| match never {}).boxed())
+        .unwrap()
+}
+
 async fn handle_audio_request(
     path: &str,
 ) -> Result<Response<BoxBody<Bytes, Infallible>>, Infallible> {
     // Extract the UUID from the URL
     // Format: /audio/uuid
     let file_id = &path[7..]; // Remove "/audio/"
+    if !is_file_id(file_id) {
+        log::warn!("Rejected audio request for invalid ID: {}", file_id);
+        let body = Full::new(Bytes::from("Audio file not found"))
+            .map_err(|never| match never {})
+            .boxed();
+
+        return Ok(Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(body)
+            .unwrap());
+    }
 
     // Create audio manager and get file path
     match AudioManager::new() {
@@ -141,7 +241,6 @@ async fn handle_audio_request(
                             .header("content-type", mime_type)
                             .header("accept-ranges", "bytes")
                             .header("cache-control", "public, max-age=3600")
-                            .header("access-control-allow-origin", "*")
                             .body(body)
                             .unwrap())
                     }
@@ -182,3 +281,108 @@ async fn handle_audio_request(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::websocket::{ClientMessage, ClientRole, PROTOCOL_VERSION, WebSocketMessage};
+    use futures_util::{SinkExt, StreamExt};
+    use std::net::SocketAddr;
+    use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+    use tokio_tungstenite::tungstenite::{self, Message};
+
+    const TOKEN: &str = "secret";
+
+    /// Starts the services with a token on a free port and returns the address they listen on.
+    async fn start(dir: &tempfile::TempDir) -> (Services, SocketAddr) {
+        let mut config = Config::default();
+        config.server.host = "127.0.0.1".to_string();
+        config.server.http_port = 0;
+        config.server.token = Some(TOKEN.to_string());
+        let config_path = dir.path().join("config.toml");
+
+        let services =
+            Services::start(&config, None, config_path.to_str().unwrap(), &config.server);
+        let addr = services
+            .server_status
+            .subscribe()
+            .wait_for(|status| status.http.addr().is_some())
+            .await
+            .unwrap()
+            .http
+            .addr()
+            .unwrap();
+        (services, addr)
+    }
+
+    /// Status of a refused handshake, or `None` if the upgrade succeeded.
+    async fn refused(url: String, origin: Option<&str>) -> Option<StatusCode> {
+        let mut request = url.into_client_request().unwrap();
+        if let Some(origin) = origin {
+            request
+                .headers_mut()
+                .insert(header::ORIGIN, origin.parse().unwrap());
+        }
+        match tokio_tungstenite::connect_async(request).await {
+            Ok(_) => None,
+            Err(tungstenite::Error::Http(response)) => Some(response.status()),
+            Err(e) => panic!("unexpected error: {}", e),
+        }
+    }
+
+    #[tokio::test]
+    async fn websocket_upgrades_need_the_token_and_our_origin() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_services, addr) = start(&dir).await;
+        let url = format!("ws://{}{}", addr, WEBSOCKET_PATH);
+        let with_token = format!("{}?token={}", url, TOKEN);
+
+        assert_eq!(refused(url, None).await, Some(StatusCode::UNAUTHORIZED));
+        assert_eq!(
+            refused(with_token.clone(), Some("http://example.com")).await,
+            Some(StatusCode::FORBIDDEN)
+        );
+        assert_eq!(refused(with_token.clone(), None).await, None);
+        assert_eq!(
+            refused(with_token, Some(&format!("http://{}", addr))).await,
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn websocket_upgrades_exchange_hellos() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_services, addr) = start(&dir).await;
+        let url = format!("ws://{}{}?token={}", addr, WEBSOCKET_PATH, TOKEN);
+        let (mut client, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+
+        let Some(Ok(Message::Text(text))) = client.next().await else {
+            panic!("no hello from the server");
+        };
+        let Ok(WebSocketMessage::Hello {
+            protocol_version, ..
+        }) = serde_json::from_str(&text)
+        else {
+            panic!("expected a hello, got {}", text);
+        };
+        assert_eq!(protocol_version, PROTOCOL_VERSION);
+
+        // Overlays of an older protocol are asked to refresh
+        let hello = ClientMessage::Hello {
+            protocol_version: PROTOCOL_VERSION - 1,
+            role: ClientRole::Overlay,
+            name: None,
+            viewport: None,
+            channel: None,
+        };
+        client.send(hello.to_message().unwrap()).await.unwrap();
+        let Some(Ok(Message::Text(text))) = client.next().await else {
+            panic!("no reply to the hello");
+        };
+        let Ok(WebSocketMessage::Toast(notice)) = serde_json::from_str(&text) else {
+            panic!("expected the refresh notice, got {}", text);
+        };
+        assert!(notice.content.contains("刷新"), "{}", notice.content);
+    }
+}
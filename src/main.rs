@@ -1,14 +1,32 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
+mod api;
 mod app;
 mod audio_manager;
+mod auth;
+mod bundle;
+mod cli;
 mod config;
+mod config_migration;
 mod fonts;
 mod http_server;
+mod markup;
+mod overlays;
+mod preview;
+mod schedule;
+mod selection;
+mod services;
+mod template;
 mod timer;
+mod timer_store;
+mod toast_queue;
+mod validation;
 mod websocket;
 
 use app::ObsReminderApp;
+use clap::Parser;
+use cli::{Cli, Command};
 use config::Config;
+use services::Services;
 
 use eframe::egui;
 use egui::viewport::IconData;
@@ -32,15 +50,49 @@ fn load_icon() -> IconData {
 }
 
 #[tokio::main]
-async fn main() -> Result<(), eframe::Error> {
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
     env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
 
-    let config_path = "config.toml";
-    let config = Config::load_or_create_default(config_path);
+    let cli = Cli::parse();
+    let config_path = cli.config;
+
+    match cli.command {
+        Some(Command::ValidateConfig) => return cli::validate_config(&config_path),
+        Some(Command::Send {
+            title,
+            content,
+            group,
+            channels,
+        }) => {
+            return cli::send_toast(&config_path, title, content, group, channels, &cli.server)
+                .await;
+        }
+        Some(Command::Profile { name }) => {
+            return cli::switch_profile(&config_path, name, &cli.server).await;
+        }
+        Some(Command::Export { path, profile }) => {
+            return cli::export_profile(&config_path, &path, profile);
+        }
+        Some(Command::Import { path }) => return cli::import_bundle(&config_path, &path),
+        Some(Command::Run { start }) => {
+            return cli::run_headless(&config_path, &cli.server, start).await;
+        }
+        None if cli.headless => {
+            return cli::run_headless(&config_path, &cli.server, cli.start).await;
+        }
+        None => {}
+    }
+
+    let (config, load_error) = Config::load_or_default(&config_path);
+    let services = Services::start(
+        &config,
+        load_error.as_ref().map(ToString::to_string),
+        &config_path,
+        &cli.server.apply(&config.server),
+    );
     let icon = load_icon();
 
     log::info!("OBS Reminder v{} starting", std::env!("CARGO_PKG_VERSION"));
-    log::info!("Configuration loaded successfully");
 
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
@@ -58,10 +110,9 @@ async fn main() -> Result<(), eframe::Error> {
             fonts::setup_custom_fonts(&cc.egui_ctx);
 
             catppuccin_egui::set_theme(&cc.egui_ctx, catppuccin_egui::MOCHA);
-            Ok(Box::new(ObsReminderApp::new(
-                config,
-                config_path.to_string(),
-            )))
+            Ok(Box::new(ObsReminderApp::new(config, services, load_error)))
         }),
-    )
+    )?;
+
+    Ok(())
 }
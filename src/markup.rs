@@ -0,0 +1,490 @@
+use serde::{Deserialize, Serialize};
+
+/// How toast titles and contents are interpreted before they reach the overlay.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub enum TextFormat {
+    /// Shown exactly as written
+    #[default]
+    #[serde(rename = "plain")]
+    Plain,
+    /// Allows `<b>`, `<i>`, `<u>`, `<s>`, `<br>` and `<span style="color: #RRGGBB">`
+    #[serde(rename = "markup")]
+    Markup,
+    /// `**bold**`, `*italic*`, `~~strike~~`, `` `code` ``, `[links](https://…)`, `<br>` and line breaks
+    #[serde(rename = "markdown")]
+    Markdown,
+}
+
+/// Formatting of a run of text. Every output (HTML, plain text, the GUI preview) is rendered
+/// from these, so nothing from the input reaches the overlay unless it is modelled here.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Style {
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub strike: bool,
+    pub code: bool,
+    pub color: Option<String>, // validated "#RRGGBB"
+    pub link: Option<String>,  // validated http(s) URL
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Segment {
+    Text(String, Style),
+    LineBreak,
+}
+
+/// Tags that may appear in markup, mapped to the tag written to the overlay.
+const ALLOWED_TAGS: [(&str, &str); 9] = [
+    ("b", "b"),
+    ("strong", "b"),
+    ("i", "i"),
+    ("em", "i"),
+    ("u", "u"),
+    ("s", "s"),
+    ("del", "s"),
+    ("span", "span"),
+    ("color", "span"),
+];
+
+pub fn parse(text: &str, format: TextFormat) -> Vec<Segment> {
+    match format {
+        TextFormat::Plain => vec![Segment::Text(text.to_string(), Style::default())],
+        TextFormat::Markup => parse_markup(text),
+        TextFormat::Markdown => parse_markdown(text),
+    }
+}
+
+/// Turns toast text into HTML that is safe to insert into the overlay.
+pub fn to_html(text: &str, format: TextFormat) -> String {
+    parse(text, format).iter().map(segment_html).collect()
+}
+
+/// The text with all formatting removed, for places that cannot show HTML.
+pub fn to_plain(text: &str, format: TextFormat) -> String {
+    parse(text, format)
+        .iter()
+        .map(|segment| match segment {
+            Segment::Text(text, _) => text.as_str(),
+            Segment::LineBreak => "\n",
+        })
+        .collect()
+}
+
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn segment_html(segment: &Segment) -> String {
+    let (text, style) = match segment {
+        Segment::Text(text, style) => (text, style),
+        Segment::LineBreak => return "<br>".to_string(),
+    };
+
+    let mut open = String::new();
+    let mut close = Vec::new();
+    if let Some(link) = &style.link {
+        open.push_str(&format!("<a href=\"{}\">", escape_html(link)));
+        close.push("</a>");
+    }
+    if let Some(color) = &style.color {
+        open.push_str(&format!("<span style=\"color:{}\">", color));
+        close.push("</span>");
+    }
+    for (enabled, tag) in [
+        (style.bold, "b"),
+        (style.italic, "i"),
+        (style.underline, "u"),
+        (style.strike, "s"),
+        (style.code, "code"),
+    ] {
+        if enabled {
+            open.push_str(&format!("<{}>", tag));
+            close.push(match tag {
+                "b" => "</b>",
+                "i" => "</i>",
+                "u" => "</u>",
+                "s" => "</s>",
+                _ => "</code>",
+            });
+        }
+    }
+
+    close.reverse();
+    format!("{}{}{}", open, escape_html(text), close.concat())
+}
+
+/// Appends text, merging it into the previous segment when the style is the same.
+fn push_text(segments: &mut Vec<Segment>, text: &str, style: &Style) {
+    if text.is_empty() {
+        return;
+    }
+    if let Some(Segment::Text(last, last_style)) = segments.last_mut()
+        && last_style == style
+    {
+        last.push_str(text);
+        return;
+    }
+    segments.push(Segment::Text(text.to_string(), style.clone()));
+}
+
+/// Keeps the allowed tags, with no attribute other than a validated color, and treats
+/// everything else as text. Unclosed tags end with the text.
+fn parse_markup(text: &str) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    // Open tags with the style that was active before each of them
+    let mut open_tags: Vec<(&'static str, Style)> = Vec::new();
+    let mut style = Style::default();
+    let mut rest = text;
+
+    while let Some(start) = rest.find('<') {
+        push_text(&mut segments, &rest[..start], &style);
+        let after = &rest[start..];
+
+        let Some(end) = after.find('>') else {
+            push_text(&mut segments, after, &style);
+            rest = "";
+            break;
+        };
+
+        match parse_tag(&after[1..end]) {
+            Some(Tag::LineBreak) => segments.push(Segment::LineBreak),
+            Some(Tag::Open(name, color)) => {
+                open_tags.push((name, style.clone()));
+                match name {
+                    "b" => style.bold = true,
+                    "i" => style.italic = true,
+                    "u" => style.underline = true,
+                    "s" => style.strike = true,
+                    _ => style.color = color,
+                }
+            }
+            Some(Tag::Close(name)) => {
+                // Only close tags that are open, along with anything opened inside them
+                if let Some(index) = open_tags.iter().rposition(|(open, _)| *open == name) {
+                    style = open_tags[index].1.clone();
+                    open_tags.truncate(index);
+                }
+            }
+            None => push_text(&mut segments, &after[..=end], &style),
+        }
+
+        rest = &after[end + 1..];
+    }
+
+    push_text(&mut segments, rest, &style);
+    segments
+}
+
+enum Tag {
+    LineBreak,
+    Open(&'static str, Option<String>),
+    Close(&'static str),
+}
+
+fn parse_tag(tag: &str) -> Option<Tag> {
+    let tag = tag.trim().trim_end_matches('/').trim_end();
+
+    if let Some(name) = tag.strip_prefix('/') {
+        return allowed_tag(name.trim()).map(Tag::Close);
+    }
+
+    let (name, attributes) = match tag.split_once(|c: char| c.is_whitespace() || c == '=') {
+        Some((name, _)) => (name, &tag[name.len()..]),
+        None => (tag, ""),
+    };
+
+    if name.eq_ignore_ascii_case("br") {
+        return Some(Tag::LineBreak);
+    }
+
+    let output = allowed_tag(name)?;
+    let color = if output == "span" {
+        Some(parse_color(attributes)?)
+    } else if attributes.trim().is_empty() {
+        None
+    } else {
+        // Formatting tags take no attributes
+        return None;
+    };
+
+    Some(Tag::Open(output, color))
+}
+
+fn allowed_tag(name: &str) -> Option<&'static str> {
+    ALLOWED_TAGS
+        .iter()
+        .find(|(input, _)| input.eq_ignore_ascii_case(name))
+        .map(|(_, output)| *output)
+}
+
+/// Accepts `style="color: #RRGGBB"`, `color="#RRGGBB"` and `<color=#RRGGBB>`.
+fn parse_color(attributes: &str) -> Option<String> {
+    let value = attributes
+        .trim()
+        .trim_start_matches("style")
+        .trim_start_matches("color")
+        .trim_start()
+        .strip_prefix('=')?
+        .trim()
+        .trim_matches(|c| c == '"' || c == '\'');
+    let color = value
+        .strip_prefix("color")
+        .map(|v| v.trim_start().trim_start_matches(':').trim())
+        .unwrap_or(value)
+        .trim_end_matches(';')
+        .trim();
+
+    let is_hex = color.len() == 7
+        && color.starts_with('#')
+        && color[1..].chars().all(|c| c.is_ascii_hexdigit());
+    is_hex.then(|| color.to_string())
+}
+
+/// Inline Markdown: emphasis, strikethrough, code spans, links and line breaks. Markers
+/// without a closing counterpart are kept as text.
+fn parse_markdown(text: &str) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    let mut style = Style::default();
+    let mut i = 0;
+
+    while i < text.len() {
+        let rest = &text[i..];
+        let c = rest.chars().next().unwrap_or_default();
+        let next = rest[c.len_utf8()..].chars().next();
+
+        match c {
+            '\n' => {
+                segments.push(Segment::LineBreak);
+                i += 1;
+            }
+            // Inline `<br>` so single line inputs can break lines too
+            '<' if line_break_tag(rest).is_some() => {
+                segments.push(Segment::LineBreak);
+                i += line_break_tag(rest).unwrap_or(1);
+            }
+            '\\' if next.is_some_and(|n| n.is_ascii_punctuation()) => {
+                let escaped = next.unwrap_or_default();
+                push_text(&mut segments, &escaped.to_string(), &style);
+                i += 1 + escaped.len_utf8();
+            }
+            '`' => match rest[1..].find('`') {
+                Some(end) => {
+                    let code = Style {
+                        code: true,
+                        ..style.clone()
+                    };
+                    push_text(&mut segments, &rest[1..=end], &code);
+                    i += end + 2;
+                }
+                None => {
+                    push_text(&mut segments, "`", &style);
+                    i += 1;
+                }
+            },
+            '[' => match parse_link(rest) {
+                Some((label, url, length)) => {
+                    let link = Style {
+                        link: Some(url.to_string()),
+                        ..style.clone()
+                    };
+                    push_text(&mut segments, label, &link);
+                    i += length;
+                }
+                None => {
+                    push_text(&mut segments, "[", &style);
+                    i += 1;
+                }
+            },
+            '*' | '_' | '~' => {
+                let marker = emphasis_marker(rest);
+                let before = text[..i].chars().next_back();
+                match marker.filter(|m| toggles(m, &style, before, &text[i + m.len()..])) {
+                    Some(marker) => {
+                        match marker {
+                            "**" | "__" => style.bold = !style.bold,
+                            "~~" => style.strike = !style.strike,
+                            _ => style.italic = !style.italic,
+                        }
+                        i += marker.len();
+                    }
+                    None => {
+                        push_text(&mut segments, &c.to_string(), &style);
+                        i += 1;
+                    }
+                }
+            }
+            _ => {
+                // Everything up to the next character that may start formatting
+                let end = rest[c.len_utf8()..]
+                    .find(['\n', '<', '\\', '`', '[', '*', '_', '~'])
+                    .map_or(rest.len(), |end| end + c.len_utf8());
+                push_text(&mut segments, &rest[..end], &style);
+                i += end;
+            }
+        }
+    }
+
+    segments
+}
+
+/// Length of a `<br>`, `<br/>` or `<br />` tag at the start of `text`.
+fn line_break_tag(text: &str) -> Option<usize> {
+    let end = text.find('>')?;
+    matches!(parse_tag(&text[1..end]), Some(Tag::LineBreak)).then_some(end + 1)
+}
+
+fn emphasis_marker(rest: &str) -> Option<&'static str> {
+    ["**", "__", "~~", "*", "_"]
+        .into_iter()
+        .find(|marker| rest.starts_with(marker))
+}
+
+/// Whether `marker` opens or closes emphasis here. Opening needs a closing marker later on the
+/// same line; `_` only counts at word boundaries so snake_case stays intact.
+fn toggles(marker: &str, style: &Style, before: Option<char>, after: &str) -> bool {
+    let is_open = match marker {
+        "**" | "__" => style.bold,
+        "~~" => style.strike,
+        _ => style.italic,
+    };
+
+    if marker.starts_with('_') {
+        let boundary = if is_open {
+            after.chars().next().is_none_or(|c| !c.is_alphanumeric())
+        } else {
+            before.is_none_or(|c| !c.is_alphanumeric())
+        };
+        if !boundary {
+            return false;
+        }
+    }
+
+    if is_open {
+        return true;
+    }
+
+    let line = after.split('\n').next().unwrap_or_default();
+    !line.starts_with(char::is_whitespace) && line.contains(marker)
+}
+
+/// Parses `[label](https://…)` at the start of `text`, returning the label, the URL and the
+/// length consumed. Only http(s) links are accepted.
+fn parse_link(text: &str) -> Option<(&str, &str, usize)> {
+    let label_end = text.find("](")?;
+    let label = &text[1..label_end];
+    if label.contains(['\n', '[']) {
+        return None;
+    }
+
+    let url_start = label_end + 2;
+    let url_end = url_start + text[url_start..].find(')')?;
+    let url = text[url_start..url_end].trim();
+
+    let valid = (url.starts_with("https://") || url.starts_with("http://"))
+        && !url.contains(char::is_whitespace);
+    valid.then_some((label, url, url_end + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_text_is_escaped() {
+        assert_eq!(
+            to_html("<script>alert('x')</script> & \"q\"", TextFormat::Plain),
+            "&lt;script&gt;alert(&#39;x&#39;)&lt;/script&gt; &amp; &quot;q&quot;"
+        );
+    }
+
+    #[test]
+    fn unknown_tags_and_attributes_stay_text() {
+        assert_eq!(
+            to_html("<script>alert(1)</script>", TextFormat::Markup),
+            "&lt;script&gt;alert(1)&lt;/script&gt;"
+        );
+        assert_eq!(
+            to_html("<b onclick=\"alert(1)\">hi</b>", TextFormat::Markup),
+            "&lt;b onclick=&quot;alert(1)&quot;&gt;hi"
+        );
+        assert_eq!(
+            to_html("<img src=x onerror=alert(1)>", TextFormat::Markup),
+            "&lt;img src=x onerror=alert(1)&gt;"
+        );
+        assert_eq!(
+            to_html("<script>alert(1)</script> **x**", TextFormat::Markdown),
+            "&lt;script&gt;alert(1)&lt;/script&gt; <b>x</b>"
+        );
+    }
+
+    #[test]
+    fn only_valid_colors_are_kept() {
+        assert_eq!(
+            to_html("<color=#FF0000>red</color>", TextFormat::Markup),
+            "<span style=\"color:#FF0000\">red</span>"
+        );
+        for text in [
+            "<color=#fff\" onload=\"alert(1)\">x</color>",
+            "<span style=\"color: #FF0000; background: url(https://example.com)\">x</span>",
+            "<color=red>x</color>",
+            "<span style=\"color:#FF0000\" onmouseover=\"alert(1)\">x</span>",
+        ] {
+            let html = to_html(text, TextFormat::Markup);
+            assert!(!html.contains("<span"), "{} -> {}", text, html);
+            assert!(!html.contains('"'), "{} -> {}", text, html);
+        }
+    }
+
+    #[test]
+    fn only_http_links_are_kept() {
+        assert_eq!(
+            to_html("[site](https://example.com)", TextFormat::Markdown),
+            "<a href=\"https://example.com\">site</a>"
+        );
+        for text in [
+            "[x](javascript:alert(1))",
+            "[x](data:text/html;base64,PHNjcmlwdD4=)",
+            "[x](JAVASCRIPT:alert(1))",
+        ] {
+            let html = to_html(text, TextFormat::Markdown);
+            assert!(!html.contains("<a"), "{} -> {}", text, html);
+        }
+        // Quotes in an accepted URL cannot end the attribute
+        assert_eq!(
+            to_html(
+                "[x](https://a.example/\"onmouseover=\"alert)",
+                TextFormat::Markdown
+            ),
+            "<a href=\"https://a.example/&quot;onmouseover=&quot;alert\">x</a>"
+        );
+    }
+
+    #[test]
+    fn nested_and_unclosed_markup_is_balanced() {
+        assert_eq!(
+            to_html("<b>bold <i>both</b> plain", TextFormat::Markup),
+            "<b>bold </b><b><i>both</i></b> plain"
+        );
+        assert_eq!(to_html("<u>open", TextFormat::Markup), "<u>open</u>");
+        assert_eq!(to_html("</i>stray", TextFormat::Markup), "stray");
+        assert_eq!(to_html("a < b", TextFormat::Markup), "a &lt; b");
+        assert_eq!(
+            to_html("**bold *both***", TextFormat::Markdown),
+            "<b>bold </b><b><i>both</i></b>"
+        );
+        assert_eq!(to_html("**open", TextFormat::Markdown), "**open");
+        assert_eq!(to_plain("<b>a</b><br>b", TextFormat::Markup), "a\nb");
+    }
+}
@@ -0,0 +1,271 @@
+use crate::websocket::ToastMessage;
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::sync::Mutex;
+
+/// Number of recent toasts whose receipts are kept.
+const MAX_RECEIPTS: usize = 20;
+
+/// A browser source that completed the handshake.
+#[derive(Debug, Clone, Serialize)]
+pub struct OverlayInfo {
+    pub name: String,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub channel: Option<String>,
+    pub protocol_version: u32,
+    pub connected_at: DateTime<Local>,
+}
+
+/// What the overlays reported back for one toast.
+#[derive(Debug, Clone, Serialize)]
+pub struct ToastReceipt {
+    pub toast_id: String,
+    pub title: String,
+    pub sent_at: DateTime<Local>,
+    // Connection ids, so an overlay that reports twice is counted once
+    delivered_to: BTreeSet<u64>,
+    shown_by: BTreeSet<u64>,
+    dismissed_by: BTreeSet<u64>,
+    pub audio_failures: Vec<AudioFailure>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AudioFailure {
+    pub overlay: String,
+    pub reason: String,
+}
+
+impl ToastReceipt {
+    /// Overlays the toast was forwarded to.
+    pub fn delivered(&self) -> usize {
+        self.delivered_to.len()
+    }
+
+    /// Overlays that confirmed the toast was put on screen.
+    pub fn shown(&self) -> usize {
+        self.shown_by.len()
+    }
+
+    pub fn dismissed(&self) -> usize {
+        self.dismissed_by.len()
+    }
+}
+
+#[derive(Default)]
+struct RegistryState {
+    next_connection: u64,
+    connections: usize,
+    overlays: BTreeMap<u64, OverlayInfo>,
+    receipts: VecDeque<ToastReceipt>,
+}
+
+/// Connected overlays and the display receipts they send, shared between the WebSocket
+/// server, the HTTP API and the GUI.
+#[derive(Default)]
+pub struct OverlayRegistry {
+    state: Mutex<RegistryState>,
+}
+
+impl OverlayRegistry {
+    /// Registers a new WebSocket connection and returns its id.
+    pub fn connect(&self) -> u64 {
+        let mut state = self.lock();
+        state.next_connection += 1;
+        state.connections += 1;
+        state.next_connection
+    }
+
+    pub fn disconnect(&self, connection: u64) {
+        let mut state = self.lock();
+        state.connections = state.connections.saturating_sub(1);
+        state.overlays.remove(&connection);
+    }
+
+    /// Marks a connection as an overlay once its hello was accepted.
+    pub fn register(&self, connection: u64, overlay: OverlayInfo) {
+        log::info!(
+            "Overlay '{}' registered ({}x{})",
+            overlay.name,
+            overlay.width.unwrap_or_default(),
+            overlay.height.unwrap_or_default()
+        );
+        self.lock().overlays.insert(connection, overlay);
+    }
+
+    pub fn toast_sent(&self, connection: u64, toast: &ToastMessage) {
+        let mut state = self.lock();
+
+        match state.receipts.iter_mut().find(|r| r.toast_id == toast.id) {
+            Some(receipt) => {
+                receipt.delivered_to.insert(connection);
+            }
+            None => {
+                if state.receipts.len() == MAX_RECEIPTS {
+                    state.receipts.pop_front();
+                }
+                state.receipts.push_back(ToastReceipt {
+                    toast_id: toast.id.clone(),
+                    title: toast.title.clone(),
+                    sent_at: Local::now(),
+                    delivered_to: BTreeSet::from([connection]),
+                    shown_by: BTreeSet::new(),
+                    dismissed_by: BTreeSet::new(),
+                    audio_failures: Vec::new(),
+                });
+            }
+        }
+    }
+
+    pub fn toast_shown(&self, connection: u64, toast_id: &str) {
+        self.update_receipt(toast_id, |receipt| {
+            receipt.shown_by.insert(connection);
+        });
+    }
+
+    pub fn toast_dismissed(&self, connection: u64, toast_id: &str) {
+        self.update_receipt(toast_id, |receipt| {
+            receipt.dismissed_by.insert(connection);
+        });
+    }
+
+    pub fn audio_failed(&self, connection: u64, toast_id: &str, reason: String) {
+        let overlay = self.overlay_name(connection);
+        log::warn!("Overlay '{}' could not play audio: {}", overlay, reason);
+        self.update_receipt(toast_id, |receipt| {
+            receipt
+                .audio_failures
+                .push(AudioFailure { overlay, reason })
+        });
+    }
+
+    /// Number of open WebSocket connections, including ones that have not said hello yet.
+    pub fn connection_count(&self) -> usize {
+        self.lock().connections
+    }
+
+    pub fn overlays(&self) -> Vec<OverlayInfo> {
+        self.lock().overlays.values().cloned().collect()
+    }
+
+    pub fn latest_receipt(&self) -> Option<ToastReceipt> {
+        self.lock().receipts.back().cloned()
+    }
+
+    fn overlay_name(&self, connection: u64) -> String {
+        self.lock()
+            .overlays
+            .get(&connection)
+            .map(|overlay| overlay.name.clone())
+            .unwrap_or_else(|| format!("#{}", connection))
+    }
+
+    fn update_receipt(&self, toast_id: &str, update: impl FnOnce(&mut ToastReceipt)) {
+        let mut state = self.lock();
+        if let Some(receipt) = state.receipts.iter_mut().find(|r| r.toast_id == toast_id) {
+            update(receipt);
+        } else {
+            log::debug!("Receipt for unknown toast {}", toast_id);
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, RegistryState> {
+        // The state stays consistent even if a holder panicked, so keep using it
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ToasterConfig;
+
+    fn overlay(name: &str) -> OverlayInfo {
+        OverlayInfo {
+            name: name.to_string(),
+            width: Some(1920),
+            height: Some(1080),
+            channel: None,
+            protocol_version: 2,
+            connected_at: Local::now(),
+        }
+    }
+
+    fn toast() -> ToastMessage {
+        ToastMessage::from_config(
+            &ToasterConfig::default(),
+            "Title".to_string(),
+            "Content".to_string(),
+        )
+    }
+
+    #[test]
+    fn receipts_count_each_overlay_once() {
+        let registry = OverlayRegistry::default();
+        let (a, b) = (registry.connect(), registry.connect());
+        let toast = toast();
+
+        registry.toast_sent(a, &toast);
+        registry.toast_sent(b, &toast);
+        registry.toast_sent(a, &toast);
+        registry.toast_shown(a, &toast.id);
+        registry.toast_shown(a, &toast.id);
+        registry.toast_shown(b, &toast.id);
+        registry.toast_dismissed(b, &toast.id);
+        registry.toast_dismissed(b, &toast.id);
+        registry.toast_shown(a, "unknown");
+
+        let receipt = registry.latest_receipt().unwrap();
+        assert_eq!(receipt.toast_id, toast.id);
+        assert_eq!(
+            (receipt.delivered(), receipt.shown(), receipt.dismissed()),
+            (2, 2, 1)
+        );
+    }
+
+    #[test]
+    fn only_recent_receipts_are_kept() {
+        let registry = OverlayRegistry::default();
+        let connection = registry.connect();
+        let first = toast();
+        registry.toast_sent(connection, &first);
+        for _ in 0..MAX_RECEIPTS {
+            registry.toast_sent(connection, &toast());
+        }
+
+        // Reports for a dropped receipt are ignored
+        registry.toast_shown(connection, &first.id);
+        assert_eq!(registry.lock().receipts.len(), MAX_RECEIPTS);
+        assert!(
+            registry
+                .lock()
+                .receipts
+                .iter()
+                .all(|r| r.toast_id != first.id)
+        );
+    }
+
+    #[test]
+    fn disconnected_overlays_are_removed() {
+        let registry = OverlayRegistry::default();
+        let (a, b) = (registry.connect(), registry.connect());
+        registry.register(a, overlay("Main"));
+        registry.register(b, overlay("Chat"));
+        assert_eq!(registry.connection_count(), 2);
+
+        registry.disconnect(a);
+        let names: Vec<String> = registry.overlays().into_iter().map(|o| o.name).collect();
+        assert_eq!(names, ["Chat"]);
+        assert_eq!(registry.connection_count(), 1);
+        assert_eq!(registry.overlay_name(a), format!("#{}", a));
+
+        // A connection that never said hello only counts as a connection
+        let c = registry.connect();
+        assert_eq!(registry.connection_count(), 2);
+        registry.disconnect(c);
+        registry.disconnect(b);
+        assert_eq!(registry.connection_count(), 0);
+        assert!(registry.overlays().is_empty());
+    }
+}
@@ -0,0 +1,218 @@
+use crate::app::hex_to_color32;
+use crate::config::{ToastDirection, ToasterConfig};
+use crate::markup::{Segment, TextFormat};
+use crate::template::{self, TemplateContext};
+use eframe::egui;
+use egui::emath::easing;
+use egui::text::{LayoutJob, TextFormat as Format};
+use egui::{Color32, FontId, Galley, Painter, Pos2, Rect, Stroke, Vec2};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use std::sync::Arc;
+
+// Sizes and timings of the overlay (svelte-toast defaults and the intro set in +page.svelte)
+const TOAST_WIDTH: f32 = 256.0;
+const TOAST_MIN_HEIGHT: f32 = 56.0;
+const TOAST_PADDING: Vec2 = Vec2::new(8.0, 12.0);
+const TOAST_MARGIN: Vec2 = Vec2::new(32.0, 24.0);
+const BAR_HEIGHT: f32 = 6.0;
+const FONT_SIZE: f32 = 16.0;
+const INTRO_SECONDS: f64 = 0.3;
+const INTRO_DISTANCE: f32 = 100.0;
+const OUTRO_SECONDS: f64 = 0.4;
+const PAUSE_SECONDS: f64 = 0.8;
+const SCENE_HEIGHT: f32 = 150.0;
+
+/// Offset that makes bold text a little wider, since the window has no bold font.
+const BOLD_OFFSET: Vec2 = Vec2::new(0.6, 0.0);
+
+/// Replays the group's toast on a small scene the way the overlay shows it: colors, text,
+/// duration bar and entry direction. Reads the settings every frame, so edits show at once.
+pub fn render_toast_preview(ui: &mut egui::Ui, group: &ToasterConfig) {
+    let Some(reminder) = group.enabled_reminders().first().copied() else {
+        ui.colored_label(Color32::GRAY, "没有可预览的提醒");
+        return;
+    };
+
+    // Variables get example values; the seed keeps {random:…} from changing every frame
+    let context = TemplateContext {
+        now: chrono::Local::now(),
+        stream_uptime: Some(std::time::Duration::ZERO),
+        count: 1,
+        next_in: Some(std::time::Duration::from_secs(
+            group.interval_time as u64 * 60,
+        )),
+    };
+    let mut rng = StdRng::seed_from_u64(0);
+    let title = template::expand(&reminder.title, &context, &mut rng);
+    let content = template::expand(&reminder.content, &context, &mut rng);
+
+    let width = ui.available_width().max(TOAST_WIDTH + TOAST_MARGIN.x * 2.0);
+    let (scene, _) = ui.allocate_exact_size(Vec2::new(width, SCENE_HEIGHT), egui::Sense::hover());
+    let mut painter = ui.painter_at(scene);
+    painter.rect_filled(scene, 4.0, Color32::from_gray(40));
+
+    let font = FontId::proportional(FONT_SIZE);
+    let text_color = hex_to_color32(&group.text_color);
+    let wrap_width = TOAST_WIDTH - TOAST_PADDING.x * 2.0;
+    let title = formatted_galleys(
+        &painter,
+        &title,
+        group.text_format,
+        &font,
+        text_color,
+        false,
+        wrap_width,
+    );
+    // The overlay wraps the content in <strong>
+    let content = formatted_galleys(
+        &painter,
+        &content,
+        group.text_format,
+        &font,
+        text_color,
+        true,
+        wrap_width,
+    );
+
+    let text_height = title[0].size().y + content[0].size().y;
+    let size = Vec2::new(
+        TOAST_WIDTH,
+        (text_height + TOAST_PADDING.y * 2.0).max(TOAST_MIN_HEIGHT),
+    );
+
+    // One cycle: slide in, show for `duration`, fade out, pause
+    let duration = group.duration as f64;
+    let cycle = INTRO_SECONDS + duration + OUTRO_SECONDS + PAUSE_SECONDS;
+    let t = ui.input(|i| i.time) % cycle;
+    let intro = easing::cubic_out((t / INTRO_SECONDS).min(1.0) as f32);
+    let outro = ((t - INTRO_SECONDS - duration) / OUTRO_SECONDS).clamp(0.0, 1.0) as f32;
+    let progress = 1.0 - ((t / duration).min(1.0) as f32);
+
+    let direction = match group.toast_direction {
+        ToastDirection::Top => Vec2::new(0.0, -1.0),
+        ToastDirection::Bottom => Vec2::new(0.0, 1.0),
+        ToastDirection::Left => Vec2::new(-1.0, 0.0),
+        ToastDirection::Right => Vec2::new(1.0, 0.0),
+    };
+    let offset = direction * INTRO_DISTANCE * (1.0 - intro);
+    let origin = Pos2::new(
+        scene.right() - TOAST_MARGIN.x - TOAST_WIDTH,
+        scene.top() + TOAST_MARGIN.y,
+    );
+    let toast = Rect::from_min_size(origin + offset, size);
+
+    painter.multiply_opacity(intro * (1.0 - outro));
+    painter.rect_filled(toast, 2.0, hex_to_color32(&group.color_1));
+
+    let bar = Rect::from_min_size(
+        Pos2::new(toast.left(), toast.bottom() - BAR_HEIGHT),
+        Vec2::new(TOAST_WIDTH * progress, BAR_HEIGHT),
+    );
+    painter.rect_filled(bar, 0.0, hex_to_color32(&group.color_2));
+
+    let text_top = toast.center().y - text_height / 2.0;
+    let title_pos = Pos2::new(toast.left() + TOAST_PADDING.x, text_top);
+    let content_pos = title_pos + Vec2::new(0.0, title[0].size().y);
+    paint_formatted(&painter, title_pos, title, text_color);
+    paint_formatted(&painter, content_pos, content, text_color);
+
+    ui.ctx().request_repaint();
+}
+
+/// Shows formatted toast text inline, the way the overlay renders it.
+pub fn render_formatted(ui: &mut egui::Ui, text: &str, format: TextFormat) {
+    let font = egui::TextStyle::Body.resolve(ui.style());
+    let color = ui.visuals().text_color();
+    let galleys = formatted_galleys(
+        ui.painter(),
+        text,
+        format,
+        &font,
+        color,
+        false,
+        ui.available_width(),
+    );
+
+    let (rect, _) = ui.allocate_exact_size(galleys[0].size(), egui::Sense::hover());
+    paint_formatted(ui.painter(), rect.min, galleys, color);
+}
+
+/// Lays out the text twice: once in full and once with only the bold parts visible, which
+/// [`paint_formatted`] draws slightly shifted to fake a bold font.
+fn formatted_galleys(
+    painter: &Painter,
+    text: &str,
+    format: TextFormat,
+    font: &FontId,
+    color: Color32,
+    strong: bool,
+    wrap_width: f32,
+) -> [Arc<Galley>; 2] {
+    let mono = FontId::monospace(font.size);
+
+    let mut job = LayoutJob::default();
+    let mut bold_job = LayoutJob::default();
+    job.wrap.max_width = wrap_width;
+    bold_job.wrap.max_width = wrap_width;
+
+    for segment in crate::markup::parse(text, format) {
+        let (text, style) = match segment {
+            Segment::Text(text, style) => (text, style),
+            Segment::LineBreak => ("\n".to_string(), Default::default()),
+        };
+
+        let color = match &style.color {
+            Some(color) => hex_to_color32(color),
+            None => color,
+        };
+        let stroke = |enabled: bool| {
+            if enabled {
+                Stroke::new(1.0, color)
+            } else {
+                Stroke::NONE
+            }
+        };
+        let format = Format {
+            font_id: if style.code {
+                mono.clone()
+            } else {
+                font.clone()
+            },
+            color,
+            // Same translucent shade as the overlay's code style
+            background: if style.code {
+                Color32::from_black_alpha(51)
+            } else {
+                Color32::TRANSPARENT
+            },
+            italics: style.italic,
+            underline: stroke(style.underline || style.link.is_some()),
+            strikethrough: stroke(style.strike),
+            ..Default::default()
+        };
+
+        let bold_format = Format {
+            color: if strong || style.bold {
+                color
+            } else {
+                Color32::TRANSPARENT
+            },
+            background: Color32::TRANSPARENT,
+            underline: Stroke::NONE,
+            strikethrough: Stroke::NONE,
+            ..format.clone()
+        };
+
+        job.append(&text, 0.0, format);
+        bold_job.append(&text, 0.0, bold_format);
+    }
+
+    [painter.layout_job(job), painter.layout_job(bold_job)]
+}
+
+fn paint_formatted(painter: &Painter, pos: Pos2, galleys: [Arc<Galley>; 2], color: Color32) {
+    let [text, bold] = galleys;
+    painter.galley(pos, text, color);
+    painter.galley(pos + BOLD_OFFSET, bold, color);
+}
@@ -0,0 +1,285 @@
+use crate::config::{Schedule, ScheduleMode, Weekday};
+use crate::validation::{IssueKind, Validation};
+use chrono::{
+    DateTime, Datelike, Days, Local, NaiveDateTime, NaiveTime, TimeDelta, TimeZone, Timelike,
+};
+use std::time::Duration;
+
+/// How far ahead to look for the next allowed fire time.
+const SEARCH_DAYS: u64 = 8;
+
+impl Schedule {
+    /// Reports the issues of the schedule at `path`.
+    pub fn check(&self, path: &str, validation: &mut Validation) {
+        match self.mode {
+            ScheduleMode::Interval => {}
+            ScheduleMode::Hourly => {
+                if self.minutes.is_empty() {
+                    validation.push(format!("{}.minutes", path), IssueKind::Empty);
+                }
+                for (i, minute) in self.minutes.iter().enumerate() {
+                    if *minute > 59 {
+                        validation.push(
+                            format!("{}.minutes[{}]", path, i),
+                            IssueKind::OutOfRange { min: 0, max: 59 },
+                        );
+                    }
+                }
+            }
+            ScheduleMode::Daily => {
+                if self.times.is_empty() {
+                    validation.push(format!("{}.times", path), IssueKind::Empty);
+                }
+                for (i, time) in self.times.iter().enumerate() {
+                    if parse_time(time).is_err() {
+                        validation.push(format!("{}.times[{}]", path, i), IssueKind::InvalidTime);
+                    }
+                }
+            }
+        }
+
+        match (&self.window_start, &self.window_end) {
+            (Some(start), Some(end)) => match (parse_time(start), parse_time(end)) {
+                (Ok(start), Ok(end)) if start == end => {
+                    validation.push(
+                        format!("{}.window_end", path),
+                        IssueKind::SameAs("window_start"),
+                    );
+                }
+                (start, end) => {
+                    if start.is_err() {
+                        validation.push(format!("{}.window_start", path), IssueKind::InvalidTime);
+                    }
+                    if end.is_err() {
+                        validation.push(format!("{}.window_end", path), IssueKind::InvalidTime);
+                    }
+                }
+            },
+            (None, None) => {}
+            (Some(_), None) => validation.push(format!("{}.window_end", path), IssueKind::Empty),
+            (None, Some(_)) => validation.push(format!("{}.window_start", path), IssueKind::Empty),
+        }
+    }
+
+    /// Returns the first fire time strictly after `after`, or `None` if nothing matches
+    /// within the next week.
+    pub fn next_after(
+        &self,
+        interval: Duration,
+        after: DateTime<Local>,
+    ) -> Option<DateTime<Local>> {
+        let after = after.naive_local();
+
+        let next = match self.mode {
+            ScheduleMode::Interval => {
+                let candidate = after + TimeDelta::from_std(interval).ok()?;
+                if self.is_allowed(candidate) {
+                    Some(candidate)
+                } else {
+                    // Outside the window or on an excluded day: wait for the next opening
+                    self.next_opening(candidate)
+                }
+            }
+            ScheduleMode::Hourly => {
+                let mut minutes = self.minutes.clone();
+                minutes.sort_unstable();
+                minutes.dedup();
+
+                let hour_start = after.date().and_hms_opt(after.time().hour(), 0, 0)?;
+                (0..SEARCH_DAYS as i64 * 24)
+                    .flat_map(|hour| {
+                        minutes.iter().map(move |minute| {
+                            hour_start + TimeDelta::hours(hour) + TimeDelta::minutes(*minute as i64)
+                        })
+                    })
+                    .find(|candidate| *candidate > after && self.is_allowed(*candidate))
+            }
+            ScheduleMode::Daily => {
+                let mut times: Vec<NaiveTime> = self
+                    .times
+                    .iter()
+                    .filter_map(|t| parse_time(t).ok())
+                    .collect();
+                times.sort_unstable();
+
+                (0..SEARCH_DAYS)
+                    .filter_map(|day| after.date().checked_add_days(Days::new(day)))
+                    .flat_map(|date| times.iter().map(move |time| date.and_time(*time)))
+                    .find(|candidate| *candidate > after && self.is_allowed(*candidate))
+            }
+        }?;
+
+        to_local(next)
+    }
+
+    fn window(&self) -> Option<(NaiveTime, NaiveTime)> {
+        let start = parse_time(self.window_start.as_deref()?).ok()?;
+        let end = parse_time(self.window_end.as_deref()?).ok()?;
+        Some((start, end))
+    }
+
+    fn is_allowed(&self, time: NaiveDateTime) -> bool {
+        let (in_window, opened_on) = match self.window() {
+            Some((start, end)) if start <= end => {
+                (time.time() >= start && time.time() <= end, time.date())
+            }
+            // A window like 22:00-02:00 wraps around midnight; the hours after it belong to
+            // the day the window opened, so a Friday window still runs early on Saturday
+            Some((start, _)) if time.time() >= start => (true, time.date()),
+            Some((_, end)) => (
+                time.time() <= end,
+                time.date().pred_opt().unwrap_or(time.date()),
+            ),
+            None => (true, time.date()),
+        };
+
+        in_window
+            && (self.weekdays.is_empty() || self.weekdays.contains(&opened_on.weekday().into()))
+    }
+
+    fn next_opening(&self, after: NaiveDateTime) -> Option<NaiveDateTime> {
+        let opening = self
+            .window()
+            .map(|(start, _)| start)
+            .unwrap_or(NaiveTime::MIN);
+
+        (0..SEARCH_DAYS)
+            .filter_map(|day| after.date().checked_add_days(Days::new(day)))
+            .map(|date| date.and_time(opening))
+            .find(|candidate| *candidate > after && self.is_allowed(*candidate))
+    }
+}
+
+impl From<chrono::Weekday> for Weekday {
+    fn from(weekday: chrono::Weekday) -> Self {
+        match weekday {
+            chrono::Weekday::Mon => Weekday::Mon,
+            chrono::Weekday::Tue => Weekday::Tue,
+            chrono::Weekday::Wed => Weekday::Wed,
+            chrono::Weekday::Thu => Weekday::Thu,
+            chrono::Weekday::Fri => Weekday::Fri,
+            chrono::Weekday::Sat => Weekday::Sat,
+            chrono::Weekday::Sun => Weekday::Sun,
+        }
+    }
+}
+
+fn parse_time(time: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(time.trim(), "%H:%M")
+        .map_err(|_| format!("Invalid schedule time '{}', expected HH:MM", time))
+}
+
+fn to_local(time: NaiveDateTime) -> Option<DateTime<Local>> {
+    // Times skipped by a DST change fire an hour later instead
+    Local.from_local_datetime(&time).earliest().or_else(|| {
+        Local
+            .from_local_datetime(&(time + TimeDelta::hours(1)))
+            .earliest()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HALF_HOUR: Duration = Duration::from_secs(30 * 60);
+
+    // 2026-01-12 is a Monday, far from any daylight saving change
+    fn at(day: u32, hour: u32, minute: u32) -> DateTime<Local> {
+        Local
+            .with_ymd_and_hms(2026, 1, day, hour, minute, 0)
+            .single()
+            .unwrap()
+    }
+
+    fn window(start: &str, end: &str) -> Schedule {
+        Schedule {
+            window_start: Some(start.to_string()),
+            window_end: Some(end.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn window_wraps_past_midnight() {
+        let schedule = window("22:00", "02:00");
+        assert_eq!(
+            schedule.next_after(HALF_HOUR, at(12, 23, 50)),
+            Some(at(13, 0, 20))
+        );
+        // 02:15 is past the end, so the next fire is when the window opens again
+        assert_eq!(
+            schedule.next_after(HALF_HOUR, at(13, 1, 45)),
+            Some(at(13, 22, 0))
+        );
+    }
+
+    #[test]
+    fn windows_past_midnight_keep_the_day_they_opened_on() {
+        // 2026-01-16 is a Friday
+        let mut schedule = window("22:00", "02:00");
+        schedule.weekdays = vec![Weekday::Fri];
+        assert_eq!(
+            schedule.next_after(HALF_HOUR, at(16, 23, 50)),
+            Some(at(17, 0, 20))
+        );
+        assert_eq!(
+            schedule.next_after(HALF_HOUR, at(15, 23, 50)),
+            Some(at(16, 22, 0))
+        );
+
+        let daily = Schedule {
+            mode: ScheduleMode::Daily,
+            times: vec!["01:00".to_string()],
+            ..schedule
+        };
+        assert_eq!(
+            daily.next_after(HALF_HOUR, at(12, 10, 0)),
+            Some(at(17, 1, 0))
+        );
+    }
+
+    #[test]
+    fn excluded_days_move_to_the_next_allowed_day() {
+        let mut schedule = window("20:00", "23:00");
+        schedule.weekdays = vec![Weekday::Wed];
+        assert_eq!(
+            schedule.next_after(HALF_HOUR, at(12, 20, 0)),
+            Some(at(14, 20, 0))
+        );
+
+        let daily = Schedule {
+            mode: ScheduleMode::Daily,
+            times: vec!["21:00".to_string(), "09:00".to_string()],
+            weekdays: vec![Weekday::Fri],
+            ..Default::default()
+        };
+        assert_eq!(
+            daily.next_after(HALF_HOUR, at(12, 10, 0)),
+            Some(at(16, 9, 0))
+        );
+    }
+
+    #[test]
+    fn no_weekdays_means_every_day() {
+        let daily = Schedule {
+            mode: ScheduleMode::Daily,
+            times: vec!["09:00".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            daily.next_after(HALF_HOUR, at(12, 10, 0)),
+            Some(at(13, 9, 0))
+        );
+
+        let hourly = Schedule {
+            mode: ScheduleMode::Hourly,
+            minutes: vec![30, 0],
+            ..Default::default()
+        };
+        assert_eq!(
+            hourly.next_after(HALF_HOUR, at(18, 23, 40)),
+            Some(at(19, 0, 0))
+        );
+    }
+}
@@ -0,0 +1,380 @@
+use crate::config::{ContentSwitchMode, Reminder};
+use rand::Rng;
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Rotation state used to pick the next reminder of a group.
+///
+/// Reminders are tracked by a key derived from their text, so adding, removing or reordering
+/// reminders does not hand their history to others, and an edited reminder starts over. The
+/// random source is passed in by the caller.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ContentSelector {
+    pub last_key: Option<String>,           // reminder shown last
+    pub bag_keys: Vec<String>,              // remaining reminders of the current shuffle-bag cycle
+    pub shown_at: BTreeMap<String, u64>,    // sequence number of the last time each was shown
+    pub shown_count: u64,                   // number of toasts picked so far
+    pub shown_times: BTreeMap<String, u64>, // number of times each was shown
+}
+
+impl ContentSelector {
+    pub fn next<R: Rng + ?Sized>(
+        &mut self,
+        mode: &ContentSwitchMode,
+        reminders: &[Reminder],
+        rng: &mut R,
+    ) -> Option<usize> {
+        let keys = reminder_keys(reminders);
+        self.forget_removed(&keys);
+
+        let enabled: Vec<usize> = (0..reminders.len())
+            .filter(|&i| reminders[i].enabled)
+            .collect();
+
+        if enabled.is_empty() {
+            return None;
+        }
+
+        let index = match mode {
+            ContentSwitchMode::Random => enabled[rng.gen_range(0..enabled.len())],
+            ContentSwitchMode::Sequential => {
+                // First enabled reminder after the one shown last, wrapping around
+                let last = self
+                    .last_key
+                    .as_ref()
+                    .and_then(|last| keys.iter().position(|key| key == last));
+                enabled
+                    .iter()
+                    .copied()
+                    .find(|&i| last.is_none_or(|last| i > last))
+                    .unwrap_or(enabled[0])
+            }
+            ContentSwitchMode::Shuffle => self.next_from_bag(&enabled, &keys, rng),
+            ContentSwitchMode::Weighted => {
+                let weights = enabled.iter().map(|&i| reminders[i].weight.max(1));
+                match WeightedIndex::new(weights) {
+                    Ok(distribution) => enabled[distribution.sample(rng)],
+                    Err(_) => enabled[rng.gen_range(0..enabled.len())],
+                }
+            }
+            ContentSwitchMode::LeastRecent => {
+                // Never shown reminders have a sequence number of 0 and come first
+                enabled
+                    .iter()
+                    .copied()
+                    .min_by_key(|&i| self.shown_at.get(&keys[i]).copied().unwrap_or(0))
+                    .unwrap_or(enabled[0])
+            }
+        };
+
+        self.record_shown(&keys[index]);
+        Some(index)
+    }
+
+    /// Drops the state of reminders that were removed or edited since they were shown.
+    fn forget_removed(&mut self, keys: &[String]) {
+        if self
+            .last_key
+            .as_ref()
+            .is_some_and(|last| !keys.contains(last))
+        {
+            self.last_key = None;
+        }
+        self.bag_keys.retain(|key| keys.contains(key));
+        self.shown_at.retain(|key, _| keys.contains(key));
+        self.shown_times.retain(|key, _| keys.contains(key));
+    }
+
+    fn next_from_bag<R: Rng + ?Sized>(
+        &mut self,
+        enabled: &[usize],
+        keys: &[String],
+        rng: &mut R,
+    ) -> usize {
+        // Drop reminders that were disabled since the bag was filled
+        let enabled_keys: Vec<&String> = enabled.iter().map(|&i| &keys[i]).collect();
+        self.bag_keys.retain(|key| enabled_keys.contains(&key));
+
+        if self.bag_keys.is_empty() {
+            self.bag_keys = enabled_keys.into_iter().cloned().collect();
+            self.bag_keys.shuffle(rng);
+
+            // The bag is drawn from the back; avoid repeating the last item of the previous cycle
+            if self.bag_keys.len() > 1 && self.bag_keys.last() == self.last_key.as_ref() {
+                let len = self.bag_keys.len();
+                let swap_with = rng.gen_range(0..len - 1);
+                self.bag_keys.swap(swap_with, len - 1);
+            }
+        }
+
+        self.bag_keys
+            .pop()
+            .and_then(|key| keys.iter().position(|k| *k == key))
+            .unwrap_or(enabled[0])
+    }
+
+    fn record_shown(&mut self, key: &str) {
+        self.shown_count += 1;
+        self.shown_at.insert(key.to_string(), self.shown_count);
+        *self.shown_times.entry(key.to_string()).or_insert(0) += 1;
+        self.last_key = Some(key.to_string());
+    }
+
+    /// How often the reminder picked last has been picked.
+    pub fn times_shown_last(&self) -> u64 {
+        self.last_key
+            .as_ref()
+            .and_then(|key| self.shown_times.get(key))
+            .copied()
+            .unwrap_or(0)
+    }
+}
+
+/// Keys identifying reminders by their title and content, since reminders have no ids.
+/// Identical reminders are told apart by their order. FNV-1a is used because the keys are
+/// persisted and `DefaultHasher` may change between Rust versions.
+fn reminder_keys(reminders: &[Reminder]) -> Vec<String> {
+    let mut keys: Vec<String> = Vec::with_capacity(reminders.len());
+    for reminder in reminders {
+        let hash = [
+            reminder.title.as_bytes(),
+            &[0xff],
+            reminder.content.as_bytes(),
+        ]
+        .concat()
+        .iter()
+        .fold(0xcbf29ce484222325_u64, |hash, &byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        });
+        let key = format!("{:016x}", hash);
+        let duplicates = keys.iter().filter(|other| other.starts_with(&key)).count();
+        keys.push(match duplicates {
+            0 => key,
+            n => format!("{}-{}", key, n),
+        });
+    }
+    keys
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    fn reminders(count: usize) -> Vec<Reminder> {
+        (0..count)
+            .map(|i| Reminder::new(&format!("title {}", i), &format!("content {}", i)))
+            .collect()
+    }
+
+    fn pick(
+        selector: &mut ContentSelector,
+        mode: ContentSwitchMode,
+        reminders: &[Reminder],
+        rng: &mut StdRng,
+        count: usize,
+    ) -> Vec<usize> {
+        (0..count)
+            .map(|_| selector.next(&mode, reminders, rng).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn shuffle_shows_every_item_once_per_cycle_without_repeats() {
+        let reminders = reminders(5);
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut selector = ContentSelector::default();
+
+        let picks = pick(
+            &mut selector,
+            ContentSwitchMode::Shuffle,
+            &reminders,
+            &mut rng,
+            50,
+        );
+
+        for cycle in picks.chunks(5) {
+            let mut sorted = cycle.to_vec();
+            sorted.sort_unstable();
+            assert_eq!(sorted, vec![0, 1, 2, 3, 4]);
+        }
+        assert!(picks.windows(2).all(|pair| pair[0] != pair[1]));
+    }
+
+    #[test]
+    fn weighted_prefers_heavier_items() {
+        let mut reminders = reminders(2);
+        reminders[1].weight = 9;
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut selector = ContentSelector::default();
+
+        let picks = pick(
+            &mut selector,
+            ContentSwitchMode::Weighted,
+            &reminders,
+            &mut rng,
+            1000,
+        );
+        let heavy = picks.iter().filter(|&&i| i == 1).count();
+
+        assert!(heavy > 800, "heavy item picked {} times", heavy);
+    }
+
+    #[test]
+    fn least_recent_cycles_through_all_items() {
+        let reminders = reminders(3);
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut selector = ContentSelector::default();
+
+        let picks = pick(
+            &mut selector,
+            ContentSwitchMode::LeastRecent,
+            &reminders,
+            &mut rng,
+            6,
+        );
+
+        assert_eq!(picks, vec![0, 1, 2, 0, 1, 2]);
+    }
+
+    #[test]
+    fn disabled_reminders_are_skipped() {
+        let mut reminders = reminders(3);
+        reminders[1].enabled = false;
+        let mut rng = StdRng::seed_from_u64(3);
+
+        for mode in [
+            ContentSwitchMode::Random,
+            ContentSwitchMode::Sequential,
+            ContentSwitchMode::Shuffle,
+            ContentSwitchMode::Weighted,
+            ContentSwitchMode::LeastRecent,
+        ] {
+            let mut selector = ContentSelector::default();
+            let picks = pick(&mut selector, mode, &reminders, &mut rng, 20);
+            assert!(picks.iter().all(|&i| i != 1));
+        }
+    }
+
+    fn titles(picks: &[usize], reminders: &[Reminder]) -> Vec<String> {
+        picks.iter().map(|&i| reminders[i].title.clone()).collect()
+    }
+
+    #[test]
+    fn state_follows_reminders_that_move() {
+        let mut reminders = reminders(3);
+        let mut rng = StdRng::seed_from_u64(5);
+        let mut selector = ContentSelector::default();
+        pick(
+            &mut selector,
+            ContentSwitchMode::Sequential,
+            &reminders,
+            &mut rng,
+            2,
+        );
+
+        // Continues after "title 1" although it is now first
+        reminders.remove(0);
+        let picks = pick(
+            &mut selector,
+            ContentSwitchMode::Sequential,
+            &reminders,
+            &mut rng,
+            2,
+        );
+        assert_eq!(titles(&picks, &reminders), ["title 2", "title 1"]);
+
+        // A new reminder at the front was never shown, unlike the one that was there before
+        reminders.insert(0, Reminder::new("new", "content"));
+        let picks = pick(
+            &mut selector,
+            ContentSwitchMode::LeastRecent,
+            &reminders,
+            &mut rng,
+            3,
+        );
+        assert_eq!(titles(&picks, &reminders), ["new", "title 2", "title 1"]);
+        assert_eq!(selector.times_shown_last(), 3);
+    }
+
+    #[test]
+    fn edited_reminders_start_over() {
+        let mut reminders = reminders(3);
+        let mut rng = StdRng::seed_from_u64(6);
+        let mut selector = ContentSelector::default();
+        pick(
+            &mut selector,
+            ContentSwitchMode::LeastRecent,
+            &reminders,
+            &mut rng,
+            4,
+        );
+        assert_eq!(selector.times_shown_last(), 2);
+
+        reminders[1].content = "edited".to_string();
+        let picks = pick(
+            &mut selector,
+            ContentSwitchMode::LeastRecent,
+            &reminders,
+            &mut rng,
+            1,
+        );
+        assert_eq!(picks, [1]);
+        assert_eq!(selector.times_shown_last(), 1);
+        assert_eq!(selector.shown_times.len(), 3);
+    }
+
+    #[test]
+    fn identical_reminders_are_told_apart() {
+        let reminders = vec![Reminder::new("same", "text"), Reminder::new("same", "text")];
+        let mut rng = StdRng::seed_from_u64(8);
+        let mut selector = ContentSelector::default();
+
+        let picks = pick(
+            &mut selector,
+            ContentSwitchMode::Sequential,
+            &reminders,
+            &mut rng,
+            4,
+        );
+        assert_eq!(picks, [0, 1, 0, 1]);
+    }
+
+    #[test]
+    fn index_based_state_of_older_versions_is_ignored() {
+        let selector: ContentSelector = toml::from_str(
+            "cursor = 2\nbag = [1, 0]\nlast = 1\nlast_shown = [1, 2]\nshown_count = 2\ntimes_shown = [1, 1]",
+        )
+        .unwrap();
+        assert_eq!(selector.shown_count, 2);
+        assert_eq!(selector.times_shown_last(), 0);
+    }
+
+    #[test]
+    fn same_seed_gives_same_sequence() {
+        let reminders = reminders(4);
+        let mut first = ContentSelector::default();
+        let mut second = ContentSelector::default();
+
+        let a = pick(
+            &mut first,
+            ContentSwitchMode::Random,
+            &reminders,
+            &mut StdRng::seed_from_u64(9),
+            10,
+        );
+        let b = pick(
+            &mut second,
+            ContentSwitchMode::Random,
+            &reminders,
+            &mut StdRng::seed_from_u64(9),
+            10,
+        );
+
+        assert_eq!(a, b);
+    }
+}
@@ -0,0 +1,389 @@
+use crate::auth::{is_authorized, is_own_origin};
+use crate::config::{Config, ServerConfig};
+use crate::http_server::{HttpServer, WEBSOCKET_PATH};
+use crate::timer::TimerHandle;
+use crate::timer_store::TimerStore;
+use crate::toast_queue::{QueueSettings, ToastQueue};
+use crate::websocket::{WebSocketMessage, WebSocketServer};
+use hyper::HeaderMap;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::net::TcpListener;
+use tokio::sync::{broadcast, watch};
+
+/// How many ports above the configured one are tried before letting the OS pick a free one.
+const PORT_FALLBACK_ATTEMPTS: u16 = 10;
+
+/// How often the configuration file is checked for changes made outside the app.
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Background services shared by the GUI, the headless mode and the HTTP API.
+#[derive(Clone)]
+pub struct Services {
+    pub config_path: String,
+    pub config: watch::Sender<Config>,
+    pub websocket_server: Arc<WebSocketServer>,
+    pub websocket_sender: broadcast::Sender<WebSocketMessage>,
+    pub toast_queue: ToastQueue,
+    pub timer: TimerHandle,
+    pub server_status: watch::Sender<ServerStatus>,
+    /// Why the configuration file on disk was not applied, if it is invalid
+    pub config_error: watch::Sender<Option<String>>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub enum ListenerState {
+    #[default]
+    Starting,
+    Listening(SocketAddr),
+    Failed(String),
+    /// Not configured to run
+    Disabled,
+}
+
+/// The addresses the servers actually bound to, which may differ from the configured ones.
+#[derive(Debug, Clone, Default)]
+pub struct ServerStatus {
+    pub http: ListenerState,
+    pub websocket: ListenerState, // the optional separate WebSocket listener
+}
+
+impl ListenerState {
+    pub fn addr(&self) -> Option<SocketAddr> {
+        match self {
+            ListenerState::Listening(addr) => Some(*addr),
+            _ => None,
+        }
+    }
+}
+
+impl ServerStatus {
+    /// URL for the OBS browser source.
+    pub fn http_url(&self) -> Option<String> {
+        self.http
+            .addr()
+            .map(|addr| format!("http://{}", display_addr(addr)))
+    }
+
+    /// WebSocket URL on the HTTP port, which works whether or not the separate listener runs.
+    pub fn websocket_url(&self) -> Option<String> {
+        self.http
+            .addr()
+            .map(|addr| format!("ws://{}{}", display_addr(addr), WEBSOCKET_PATH))
+    }
+
+    pub fn separate_websocket_url(&self) -> Option<String> {
+        self.websocket
+            .addr()
+            .map(|addr| format!("ws://{}", display_addr(addr)))
+    }
+}
+
+impl Services {
+    /// Starts the WebSocket server, the HTTP server and the timer task. `server` is passed
+    /// separately from `config` so command line overrides are never saved. With a
+    /// `load_error`, `config` is only a placeholder and the timers wait for a valid one.
+    pub fn start(
+        config: &Config,
+        load_error: Option<String>,
+        config_path: &str,
+        server: &ServerConfig,
+    ) -> Self {
+        if server.token.is_none() && !is_loopback_host(&server.host) {
+            log::warn!(
+                "Listening on {} without a token: anyone on the network can control OBS Reminder",
+                server.host
+            );
+        }
+
+        let websocket_server = Arc::new(WebSocketServer::new(QueueSettings::from_config(
+            &config.app,
+        )));
+        let websocket_sender = websocket_server.get_sender();
+        let toast_queue = websocket_server.queue().clone();
+        let (server_status, _) = watch::channel(ServerStatus::default());
+
+        // The timer runs in its own task so toasts are sent even when the window is not repainted
+        let timer = TimerHandle::spawn(
+            load_error.is_none().then(|| config.clone()),
+            websocket_sender.clone(),
+            toast_queue.clone(),
+            TimerStore::path_for_config(config_path),
+        );
+
+        let (config_sender, _) = watch::channel(config.clone());
+        let (config_error, _) = watch::channel(load_error);
+        let services = Self {
+            config_path: config_path.to_string(),
+            config: config_sender,
+            websocket_server,
+            websocket_sender,
+            toast_queue,
+            timer,
+            server_status,
+            config_error,
+        };
+
+        services.spawn_config_watcher();
+
+        // The separate WebSocket listener is optional since the HTTP server accepts upgrades
+        if let Some(websocket_port) = server.websocket_port {
+            services.spawn_websocket_listener(&server.host, websocket_port, server.port_fallback);
+        } else {
+            services
+                .server_status
+                .send_modify(|s| s.websocket = ListenerState::Disabled);
+        }
+
+        // Initialize and start HTTP server
+        let http_services = services.clone();
+        let http_config = server.clone();
+        tokio::spawn(async move {
+            let status = http_services.server_status.clone();
+            let Some(listener) = bind_listener(
+                &http_config.host,
+                http_config.http_port,
+                http_config.port_fallback,
+                &status,
+                |s| &mut s.http,
+            )
+            .await
+            else {
+                return;
+            };
+
+            let http_server = HttpServer::new(http_services);
+            if let Err(e) = http_server.start(listener).await {
+                log::error!("HTTP server error: {}", e);
+                status.send_modify(|s| s.http = ListenerState::Failed(e.to_string()));
+            }
+        });
+
+        services
+    }
+
+    fn spawn_websocket_listener(&self, host: &str, port: u16, fallback: bool) {
+        let services = self.clone();
+        let host = host.to_string();
+        tokio::spawn(async move {
+            let status = &services.server_status;
+            let Some(listener) =
+                bind_listener(&host, port, fallback, status, |s| &mut s.websocket).await
+            else {
+                return;
+            };
+
+            let auth_services = services.clone();
+            let authorize = move |headers: &HeaderMap, query: Option<&str>| {
+                auth_services.is_own_origin(headers) && auth_services.is_authorized(headers, query)
+            };
+
+            if let Err(e) = services.websocket_server.start(listener, authorize).await {
+                log::error!("WebSocket server error: {}", e);
+                status.send_modify(|s| s.websocket = ListenerState::Failed(e.to_string()));
+            }
+        });
+    }
+
+    /// Reloads the configuration file whenever its modification time or size changes.
+    fn spawn_config_watcher(&self) {
+        let services = self.clone();
+        tokio::spawn(async move {
+            let path = services.config_path.clone();
+            let mut last_seen = file_stamp(&path);
+            let mut interval = tokio::time::interval(CONFIG_POLL_INTERVAL);
+
+            loop {
+                interval.tick().await;
+                let stamp = file_stamp(&path);
+                if stamp == last_seen {
+                    continue;
+                }
+                last_seen = stamp;
+
+                // A deleted file is recreated on the next save; keep running meanwhile
+                if last_seen.is_some() {
+                    services.reload_config();
+                }
+            }
+        });
+    }
+
+    /// Applies the configuration file if it is valid and differs from the running one. An
+    /// invalid file is reported through `config_error` and the running configuration is kept.
+    pub fn reload_config(&self) {
+        let config = match Config::load_from_file(&self.config_path) {
+            Ok(config) => config,
+            Err(e) => {
+                log::warn!(
+                    "Keeping the current configuration, {} is invalid: {}",
+                    self.config_path,
+                    e
+                );
+                self.config_error.send_replace(Some(e.to_string()));
+                return;
+            }
+        };
+
+        let was_invalid = self.config_error.send_replace(None).is_some();
+
+        // Our own saves show up here too. A file fixed after a broken start is applied even if
+        // it matches the placeholder, since the timers are waiting for it
+        let unchanged = config.same_as(&self.config.borrow());
+        if !unchanged || was_invalid {
+            log::info!("Configuration reloaded from {}", self.config_path);
+            self.apply_config(config);
+        }
+    }
+
+    /// Checks the token of the applied configuration, so a new token works without a restart.
+    pub fn is_authorized(&self, headers: &HeaderMap, query: Option<&str>) -> bool {
+        is_authorized(self.config.borrow().server.token.as_deref(), headers, query)
+    }
+
+    /// Checks that a request or WebSocket handshake does not come from a foreign web page.
+    pub fn is_own_origin(&self, headers: &HeaderMap) -> bool {
+        let http_port = self
+            .server_status
+            .borrow()
+            .http
+            .addr()
+            .map(|addr| addr.port());
+        is_own_origin(headers, http_port)
+    }
+
+    /// Applies a new configuration to the running timer and notifies all observers.
+    pub fn apply_config(&self, config: Config) {
+        self.timer.update_config(&config);
+        self.toast_queue
+            .update_settings(QueueSettings::from_config(&config.app));
+        self.config.send_replace(config);
+        let _ = self.websocket_sender.send(WebSocketMessage::ConfigChanged);
+    }
+
+    /// Validates, saves and applies a configuration.
+    pub fn save_config(&self, config: Config) -> Result<(), Box<dyn std::error::Error>> {
+        config.save_to_file(&self.config_path)?;
+        self.config_error.send_replace(None);
+        self.apply_config(config);
+        Ok(())
+    }
+
+    /// Saves and applies `config` with `name` as the active profile. If any timer was running,
+    /// the groups of the new profile start right away.
+    pub fn switch_profile(
+        &self,
+        mut config: Config,
+        name: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if config.profile(name).is_none() {
+            return Err(format!("Unknown profile: {}", name).into());
+        }
+
+        let was_running = self.timer.state().is_running();
+        config.active_profile = name.to_string();
+        self.save_config(config)?;
+        if was_running {
+            self.timer.start(None);
+        }
+
+        log::info!("Switched to profile {}", name);
+        Ok(())
+    }
+}
+
+/// Binds `host:port`, falling back to the next ports and finally to any free port when the
+/// port is taken and `fallback` is set. The outcome is reported through `status`.
+async fn bind_listener(
+    host: &str,
+    port: u16,
+    fallback: bool,
+    status: &watch::Sender<ServerStatus>,
+    state: fn(&mut ServerStatus) -> &mut ListenerState,
+) -> Option<TcpListener> {
+    let mut result = TcpListener::bind((host, port)).await;
+
+    if fallback && result.is_err() {
+        let candidates = (1..=PORT_FALLBACK_ATTEMPTS)
+            .filter_map(|offset| port.checked_add(offset))
+            .chain([0]);
+
+        for candidate in candidates {
+            if let Ok(listener) = TcpListener::bind((host, candidate)).await {
+                result = Ok(listener);
+                break;
+            }
+        }
+    }
+
+    match result.and_then(|listener| Ok((listener.local_addr()?, listener))) {
+        Ok((addr, listener)) => {
+            if addr.port() != port {
+                log::warn!(
+                    "Port {} on {} is not available, using {} instead",
+                    port,
+                    host,
+                    addr.port()
+                );
+            }
+            status.send_modify(|s| *state(s) = ListenerState::Listening(addr));
+            Some(listener)
+        }
+        Err(e) => {
+            let error = format!("Failed to listen on {}:{}: {}", host, port, e);
+            log::error!("{}", error);
+            status.send_modify(|s| *state(s) = ListenerState::Failed(error));
+            None
+        }
+    }
+}
+
+fn file_stamp(path: impl AsRef<Path>) -> Option<(SystemTime, u64)> {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+pub fn is_loopback_host(host: &str) -> bool {
+    host == "localhost"
+        || host
+            .parse::<std::net::IpAddr>()
+            .is_ok_and(|ip| ip.is_loopback())
+}
+
+/// Addresses bound to every interface are reachable through localhost.
+fn display_addr(addr: SocketAddr) -> String {
+    if addr.ip().is_unspecified() {
+        format!("localhost:{}", addr.port())
+    } else {
+        addr.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn taken_ports_fall_back_to_a_free_one() {
+        let taken = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+        let port = taken.local_addr().unwrap().port();
+        let (status, _) = watch::channel(ServerStatus::default());
+
+        let listener = bind_listener("127.0.0.1", port, true, &status, |s| &mut s.http)
+            .await
+            .expect("a fallback port");
+        let addr = listener.local_addr().unwrap();
+        assert_ne!(addr.port(), port);
+        assert_eq!(status.borrow().http.addr(), Some(addr));
+
+        let (status, _) = watch::channel(ServerStatus::default());
+        assert!(
+            bind_listener("127.0.0.1", port, false, &status, |s| &mut s.http)
+                .await
+                .is_none()
+        );
+        assert!(matches!(status.borrow().http, ListenerState::Failed(_)));
+    }
+}
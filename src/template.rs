@@ -0,0 +1,223 @@
+use chrono::{DateTime, Local};
+use rand::Rng;
+use rand::seq::SliceRandom;
+use std::time::Duration;
+
+/// Variables that may appear in reminder titles and contents as `{name}`.
+pub const VARIABLES: [&str; 6] = [
+    "time",
+    "date",
+    "stream_uptime",
+    "count",
+    "next_in",
+    "random",
+];
+
+/// Values substituted into a reminder when its toast is sent.
+#[derive(Debug, Clone)]
+pub struct TemplateContext {
+    pub now: DateTime<Local>,
+    pub stream_uptime: Option<Duration>, // time since the group's timer was started
+    pub count: u64,                      // times this reminder has been shown, including now
+    pub next_in: Option<Duration>,       // time until the group's next toast
+}
+
+enum Part<'a> {
+    Text(&'a str),
+    Variable(&'a str, Option<&'a str>), // name and the argument after ':'
+    /// Shaped like a variable but not one, kept as written
+    Unknown(&'a str, &'a str), // name and the placeholder with its braces
+}
+
+/// Replaces `{variable}` placeholders. `{{` and `}}` stand for literal braces; any other brace
+/// that is not part of a known variable is kept as written, so texts from before variables
+/// existed show unchanged.
+pub fn expand<R: Rng + ?Sized>(text: &str, context: &TemplateContext, rng: &mut R) -> String {
+    let mut expanded = String::with_capacity(text.len());
+    for part in parse(text) {
+        match part {
+            Part::Text(text) | Part::Unknown(_, text) => expanded.push_str(text),
+            Part::Variable(name, argument) => match (name, argument) {
+                ("time", None) => expanded.push_str(&context.now.format("%H:%M").to_string()),
+                ("date", None) => expanded.push_str(&context.now.format("%Y-%m-%d").to_string()),
+                ("stream_uptime", None) => {
+                    expanded.push_str(&format_duration(context.stream_uptime.unwrap_or_default()))
+                }
+                ("count", None) => expanded.push_str(&context.count.to_string()),
+                ("next_in", None) => match context.next_in {
+                    Some(next_in) => expanded.push_str(&format_duration(next_in)),
+                    None => expanded.push('-'),
+                },
+                ("random", Some(options)) => {
+                    let options: Vec<&str> = options.split('|').collect();
+                    expanded.push_str(options.choose(rng).copied().unwrap_or_default());
+                }
+                _ => {
+                    expanded.push('{');
+                    expanded.push_str(name);
+                    if let Some(argument) = argument {
+                        expanded.push(':');
+                        expanded.push_str(argument);
+                    }
+                    expanded.push('}');
+                }
+            },
+        }
+    }
+    expanded
+}
+
+/// Checks that every variable is used with the right arguments.
+pub fn validate(text: &str) -> Result<(), String> {
+    for part in parse(text) {
+        let Part::Variable(name, argument) = part else {
+            continue;
+        };
+
+        match (name, argument) {
+            ("random", Some(options)) if !options.is_empty() => {}
+            ("random", _) => {
+                return Err("{random} needs options, e.g. {random:a|b|c}".to_string());
+            }
+            (_, None) => {}
+            (name, Some(_)) => {
+                return Err(format!("Variable {{{}}} takes no argument", name));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Placeholders like `{name}` or `{name:…}` whose name is no variable. They are shown as
+/// written, but usually are typos.
+pub fn unknown_variables(text: &str) -> Vec<&str> {
+    parse(text)
+        .into_iter()
+        .filter_map(|part| match part {
+            Part::Unknown(name, _) => Some(name),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Splits a text into literal parts and variables. Braces around anything that is not shaped
+/// like a variable name, such as JSON or a lone `{`, are text.
+fn parse(text: &str) -> Vec<Part<'_>> {
+    let mut parts = Vec::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find(['{', '}']) {
+        parts.push(Part::Text(&rest[..start]));
+        let after = &rest[start..];
+
+        if after.starts_with("{{") || after.starts_with("}}") {
+            parts.push(Part::Text(&after[..1]));
+            rest = &after[2..];
+            continue;
+        }
+
+        let variable = after
+            .strip_prefix('{')
+            .and_then(|after| after.split_once('}'))
+            .and_then(|(placeholder, rest)| {
+                let (name, argument) = match placeholder.split_once(':') {
+                    Some((name, argument)) => (name.trim(), Some(argument)),
+                    None => (placeholder.trim(), None),
+                };
+                let part = if VARIABLES.contains(&name) {
+                    Part::Variable(name, argument)
+                } else if is_identifier(name) {
+                    Part::Unknown(name, &after[..placeholder.len() + 2])
+                } else {
+                    return None;
+                };
+                Some((part, rest))
+            });
+        match variable {
+            Some((variable, after_variable)) => {
+                parts.push(variable);
+                rest = after_variable;
+            }
+            None => {
+                parts.push(Part::Text(&after[..1]));
+                rest = &after[1..];
+            }
+        }
+    }
+
+    parts.push(Part::Text(rest));
+    parts
+}
+
+fn is_identifier(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// `1:05:09` or `5:09`, like the countdown in the window.
+fn format_duration(duration: Duration) -> String {
+    let total_seconds = duration.as_secs();
+    let hours = total_seconds / 3600;
+    let minutes = total_seconds % 3600 / 60;
+    let seconds = total_seconds % 60;
+
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, seconds)
+    } else {
+        format!("{}:{:02}", minutes, seconds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    fn expand_at_nine(text: &str) -> String {
+        let context = TemplateContext {
+            now: Local.with_ymd_and_hms(2026, 1, 12, 21, 5, 0).unwrap(),
+            stream_uptime: Some(Duration::from_secs(3909)),
+            count: 3,
+            next_in: None,
+        };
+        expand(text, &context, &mut StdRng::seed_from_u64(0))
+    }
+
+    #[test]
+    fn variables_are_replaced() {
+        assert_eq!(
+            expand_at_nine("{time} {date} {stream_uptime} #{count} {next_in}"),
+            "21:05 2026-01-12 1:05:09 #3 -"
+        );
+        assert_eq!(expand_at_nine("{random:ok}"), "ok");
+        assert_eq!(expand_at_nine("{{time}} }}"), "{time} }");
+    }
+
+    #[test]
+    fn other_braces_are_text() {
+        // Texts written before variables existed must keep working
+        for text in [
+            "记得喝水 {",
+            "} 休息一下",
+            "{喝水} {name}",
+            "{ {time}",
+            "JSON: {\"a\": 1}",
+        ] {
+            assert_eq!(validate(text), Ok(()), "{}", text);
+        }
+        assert_eq!(expand_at_nine("{喝水} {name}"), "{喝水} {name}");
+        assert_eq!(unknown_variables("{喝水} {name} {{time}} {x y}"), ["name"]);
+        assert_eq!(expand_at_nine("{ {time}"), "{ 21:05");
+        assert_eq!(expand_at_nine("}{count}{"), "}3{");
+    }
+
+    #[test]
+    fn known_variables_need_the_right_arguments() {
+        assert!(validate("{time:x}").is_err());
+        assert!(validate("{random}").is_err());
+        assert!(validate("{random:}").is_err());
+        assert_eq!(expand_at_nine("{time:x}"), "{time:x}");
+    }
+}
@@ -1,23 +1,44 @@
-use crate::config::{Config, ContentSwitchMode};
-use rand::Rng;
-use std::time::{Duration, Instant};
+use crate::config::{
+    Config, ContentSwitchMode, MissedToastPolicy, Reminder, Schedule, ToasterConfig,
+};
+use crate::selection::ContentSelector;
+use crate::template::{self, TemplateContext};
+use crate::timer_store::{GroupTimerRecord, TimerStore};
+use crate::toast_queue::ToastQueue;
+use crate::websocket::{CountdownEntry, ToastMessage, WebSocketMessage};
+use chrono::{DateTime, Local};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc, watch};
+
+/// Upper bound for a single sleep, so wall-clock changes and suspend are noticed quickly.
+const MAX_SLEEP: Duration = Duration::from_secs(1);
+
+/// Delay before a missed toast is shown after launch with `MissedToastPolicy::FireOnce`.
+const MISSED_TOAST_DELAY: Duration = Duration::from_secs(10);
 
 pub struct TimerService {
+    pub group_id: String,
     pub interval: Duration,
+    pub schedule: Schedule,
     pub is_running: bool,
-    pub content_index: usize,
+    pub started_at: Option<DateTime<Local>>, // for {stream_uptime}
+    pub selector: ContentSelector,
     pub switch_mode: ContentSwitchMode,
-    pub last_toast_time: Option<Instant>,
-    pub next_toast_time: Option<Instant>,
+    pub last_toast_time: Option<DateTime<Local>>,
+    pub next_toast_time: Option<DateTime<Local>>,
 }
 
 impl TimerService {
-    pub fn new(config: &Config) -> Self {
+    pub fn new(group: &ToasterConfig) -> Self {
         Self {
-            interval: Duration::from_secs(config.toaster.interval_time as u64 * 60),
+            group_id: group.id.clone(),
+            interval: Duration::from_secs(group.interval_time as u64 * 60),
+            schedule: group.schedule.clone(),
             is_running: false,
-            content_index: 0,
-            switch_mode: config.toaster.content_switch_mode.clone(),
+            started_at: None,
+            selector: ContentSelector::default(),
+            switch_mode: group.content_switch_mode.clone(),
             last_toast_time: None,
             next_toast_time: None,
         }
@@ -25,34 +46,23 @@ impl TimerService {
 
     pub fn start(&mut self) {
         self.is_running = true;
-        let now = Instant::now();
+        let now = Local::now();
+        self.started_at = Some(now);
         self.last_toast_time = Some(now);
-        self.next_toast_time = Some(now + self.interval);
-        log::info!("Timer service started");
+        self.next_toast_time = self.schedule.next_after(self.interval, now);
+        log::info!("Timer service started for group {}", self.group_id);
+
+        if self.next_toast_time.is_none() {
+            log::warn!("Schedule has no upcoming fire time within the next week");
+        }
     }
 
     pub fn stop(&mut self) {
         self.is_running = false;
+        self.started_at = None;
         self.last_toast_time = None;
         self.next_toast_time = None;
-        log::info!("Timer service stopped");
-    }
-
-    pub fn get_time_until_next_toast(&self) -> Option<Duration> {
-        if !self.is_running {
-            return None;
-        }
-
-        if let Some(next_time) = self.next_toast_time {
-            let now = Instant::now();
-            if next_time > now {
-                Some(next_time - now)
-            } else {
-                Some(Duration::from_secs(0))
-            }
-        } else {
-            None
-        }
+        log::info!("Timer service stopped for group {}", self.group_id);
     }
 
     pub fn should_send_toast(&mut self) -> bool {
@@ -61,11 +71,11 @@ impl TimerService {
         }
 
         if let Some(next_time) = self.next_toast_time {
-            let now = Instant::now();
+            let now = Local::now();
             if now >= next_time {
                 // Update times for next toast
                 self.last_toast_time = Some(now);
-                self.next_toast_time = Some(now + self.interval);
+                self.next_toast_time = self.schedule.next_after(self.interval, now);
                 return true;
             }
         }
@@ -73,52 +83,486 @@ impl TimerService {
         false
     }
 
-    pub fn _reset_timer(&mut self) {
+    pub fn reset_timer(&mut self) {
         if self.is_running {
-            let now = Instant::now();
+            let now = Local::now();
             self.last_toast_time = Some(now);
-            self.next_toast_time = Some(now + self.interval);
+            self.next_toast_time = self.schedule.next_after(self.interval, now);
         }
     }
 
-    pub fn get_next_content(&mut self, titles: &[String], contents: &[String]) -> (String, String) {
-        if titles.is_empty() || contents.is_empty() {
-            log::warn!("No titles or contents available, using defaults");
-            return ("Default Title".to_string(), "Default Content".to_string());
-        }
-
-        let (title, content) = match self.switch_mode {
-            ContentSwitchMode::Random => {
-                let mut rng = rand::thread_rng();
-                let title_idx = rng.gen_range(0..titles.len());
-                let content_idx = rng.gen_range(0..contents.len());
-                (titles[title_idx].clone(), contents[content_idx].clone())
-            }
-            ContentSwitchMode::Sequential => {
-                let title = titles[self.content_index % titles.len()].clone();
-                let content = contents[self.content_index % contents.len()].clone();
-                self.content_index = (self.content_index + 1) % titles.len().max(contents.len());
-                (title, content)
-            }
+    pub fn get_next_content<'a>(&mut self, reminders: &'a [Reminder]) -> Option<&'a Reminder> {
+        let mut rng = rand::thread_rng();
+        let Some(index) = self.selector.next(&self.switch_mode, reminders, &mut rng) else {
+            log::warn!("No enabled reminders available");
+            return None;
         };
 
-        log::debug!("Selected content - Title: {}, Content: {}", title, content);
-        (title, content)
+        let reminder = &reminders[index];
+        log::debug!(
+            "Selected content - Title: {}, Content: {}",
+            reminder.title,
+            reminder.content
+        );
+        Some(reminder)
     }
 
-    pub fn update_config(&mut self, config: &Config) {
-        let new_interval = Duration::from_secs(config.toaster.interval_time as u64 * 60);
+    pub fn update_config(&mut self, group: &ToasterConfig) {
+        let new_interval = Duration::from_secs(group.interval_time as u64 * 60);
+        let new_schedule = group.schedule.clone();
 
-        // If interval changed and timer is running, adjust next toast time
-        if self.interval != new_interval
+        // If the schedule changed and timer is running, adjust next toast time
+        if (self.interval != new_interval || self.schedule != new_schedule)
             && self.is_running
             && let Some(last_time) = self.last_toast_time
         {
-            self.next_toast_time = Some(last_time + new_interval);
+            self.next_toast_time = new_schedule.next_after(new_interval, last_time);
         }
 
         self.interval = new_interval;
-        self.switch_mode = config.toaster.content_switch_mode.clone();
-        log::info!("Timer service configuration updated");
+        self.schedule = new_schedule;
+        self.switch_mode = group.content_switch_mode.clone();
+        log::debug!(
+            "Timer service configuration updated for group {}",
+            self.group_id
+        );
+    }
+
+    /// Restores a persisted timer, applying the missed toast policy to a fire time that
+    /// passed while the app was closed.
+    pub fn restore(&mut self, record: &GroupTimerRecord, policy: MissedToastPolicy) {
+        self.selector = record.selector.clone();
+
+        if !record.is_running {
+            return;
+        }
+
+        let now = Local::now();
+        self.is_running = true;
+        self.started_at = record.started_at.or(record.last_toast_time);
+        self.last_toast_time = record.last_toast_time;
+        self.next_toast_time = match record.next_toast_time {
+            Some(next_time) if next_time < now => match policy {
+                MissedToastPolicy::Skip => {
+                    log::info!(
+                        "Skipping toast missed at {} for group {}",
+                        next_time,
+                        self.group_id
+                    );
+                    self.schedule.next_after(self.interval, now)
+                }
+                // Give overlays a moment to reconnect before catching up
+                MissedToastPolicy::FireOnce => Some(now + MISSED_TOAST_DELAY),
+            },
+            Some(next_time) => Some(next_time),
+            None => self.schedule.next_after(self.interval, now),
+        };
+
+        log::info!("Timer restored for group {}", self.group_id);
+    }
+
+    /// Values for the template variables of the reminder picked last.
+    fn template_context(&self) -> TemplateContext {
+        let now = Local::now();
+        TemplateContext {
+            now,
+            stream_uptime: self
+                .started_at
+                .and_then(|started_at| (now - started_at).to_std().ok()),
+            count: self.selector.times_shown_last(),
+            next_in: self
+                .next_toast_time
+                .and_then(|next_time| (next_time - now).to_std().ok()),
+        }
+    }
+
+    fn record(&self) -> GroupTimerRecord {
+        GroupTimerRecord {
+            group_id: self.group_id.clone(),
+            is_running: self.is_running,
+            started_at: self.started_at,
+            last_toast_time: self.last_toast_time,
+            next_toast_time: self.next_toast_time,
+            selector: self.selector.clone(),
+        }
+    }
+
+    fn state(&self, group: &ToasterConfig) -> GroupTimerState {
+        GroupTimerState {
+            group_id: self.group_id.clone(),
+            group_name: group.name.clone(),
+            is_running: self.is_running,
+            next_toast_time: self.next_toast_time,
+        }
+    }
+}
+
+/// Commands accepted by the background timer task. `None` targets every group.
+#[derive(Debug)]
+pub enum TimerCommand {
+    Start(Option<String>),
+    Stop(Option<String>),
+    /// Skip the pending toast and restart the countdown
+    Skip(Option<String>),
+    UpdateConfig(Box<Config>),
+}
+
+/// Snapshot of all group timers published after every change.
+#[derive(Debug, Clone, Default)]
+pub struct TimerState {
+    pub groups: Vec<GroupTimerState>,
+}
+
+#[derive(Debug, Clone)]
+pub struct GroupTimerState {
+    pub group_id: String,
+    pub group_name: String,
+    pub is_running: bool,
+    pub next_toast_time: Option<DateTime<Local>>,
+}
+
+impl TimerState {
+    /// Whether any group is running.
+    pub fn is_running(&self) -> bool {
+        self.groups.iter().any(|group| group.is_running)
+    }
+}
+
+impl GroupTimerState {
+    pub fn time_until_next_toast(&self) -> Option<Duration> {
+        if !self.is_running {
+            return None;
+        }
+
+        self.next_toast_time
+            .map(|next_time| (next_time - Local::now()).to_std().unwrap_or_default())
+    }
+}
+
+/// Handle to the timer task, which owns one `TimerService` per group and sends toasts on its own
+/// so reminders do not depend on the GUI being repainted.
+#[derive(Clone)]
+pub struct TimerHandle {
+    commands: mpsc::UnboundedSender<TimerCommand>,
+    state: watch::Receiver<TimerState>,
+}
+
+impl TimerHandle {
+    /// Spawns the timer task, restoring the timers persisted at `store_path`. Without a
+    /// configuration the task waits for the first [`TimerHandle::update_config`] before it
+    /// touches the persisted state.
+    pub fn spawn(
+        config: Option<Config>,
+        sender: broadcast::Sender<WebSocketMessage>,
+        queue: ToastQueue,
+        store_path: PathBuf,
+    ) -> Self {
+        let (command_tx, command_rx) = mpsc::unbounded_channel();
+        let (state_tx, state_rx) = watch::channel(TimerState::default());
+
+        tokio::spawn(run_timer(
+            config, sender, queue, command_rx, state_tx, store_path,
+        ));
+
+        Self {
+            commands: command_tx,
+            state: state_rx,
+        }
+    }
+
+    pub fn start(&self, group_id: Option<String>) {
+        self.send(TimerCommand::Start(group_id));
+    }
+
+    pub fn stop(&self, group_id: Option<String>) {
+        self.send(TimerCommand::Stop(group_id));
+    }
+
+    pub fn skip(&self, group_id: Option<String>) {
+        self.send(TimerCommand::Skip(group_id));
+    }
+
+    pub fn update_config(&self, config: &Config) {
+        self.send(TimerCommand::UpdateConfig(Box::new(config.clone())));
+    }
+
+    pub fn state(&self) -> TimerState {
+        self.state.borrow().clone()
+    }
+
+    fn send(&self, command: TimerCommand) {
+        if let Err(e) = self.commands.send(command) {
+            log::error!("Timer task is not running: {:?}", e.0);
+        }
+    }
+}
+
+async fn run_timer(
+    config: Option<Config>,
+    sender: broadcast::Sender<WebSocketMessage>,
+    queue: ToastQueue,
+    mut commands: mpsc::UnboundedReceiver<TimerCommand>,
+    state: watch::Sender<TimerState>,
+    store_path: PathBuf,
+) {
+    // Records of groups that are not in the configuration would be pruned, so a broken
+    // config.toml must not get that far
+    let config = match config {
+        Some(config) => Some(config),
+        None => wait_for_config(&mut commands).await,
+    };
+    let Some(mut config) = config else {
+        return;
+    };
+
+    // Records of groups without a timer, i.e. those of the other profiles
+    let mut parked = TimerStore::load(&store_path).groups;
+    let mut timers = update_timers(Vec::new(), &config, &mut parked);
+    state.send_replace(timer_state(&timers, &config));
+
+    loop {
+        let next_toast_time = timers
+            .iter()
+            .filter(|timer| timer.is_running)
+            .filter_map(|timer| timer.next_toast_time)
+            .min();
+
+        let mut changed = true;
+
+        tokio::select! {
+            command = commands.recv() => match command {
+                Some(TimerCommand::Start(group_id)) => {
+                    for timer in select_timers(&mut timers, group_id.as_deref()) {
+                        if !timer.is_running {
+                            timer.start();
+                        }
+                    }
+                }
+                Some(TimerCommand::Stop(group_id)) => {
+                    for timer in select_timers(&mut timers, group_id.as_deref()) {
+                        if timer.is_running {
+                            timer.stop();
+                        }
+                    }
+                }
+                Some(TimerCommand::Skip(group_id)) => {
+                    for timer in select_timers(&mut timers, group_id.as_deref()) {
+                        timer.reset_timer();
+                        log::info!("Pending toast skipped for group {}", timer.group_id);
+                    }
+                }
+                Some(TimerCommand::UpdateConfig(new_config)) => {
+                    config = *new_config;
+                    timers = update_timers(timers, &config, &mut parked);
+                    log::info!("Timer service configuration updated");
+                }
+                None => {
+                    log::debug!("Timer command channel closed, stopping timer task");
+                    break;
+                }
+            },
+            _ = sleep_until(next_toast_time) => {
+                changed = false;
+                for timer in timers.iter_mut() {
+                    if timer.should_send_toast()
+                        && let Some(group) = config.group(&timer.group_id)
+                    {
+                        send_automatic_toast(timer, group, &queue);
+                        changed = true;
+                    }
+                }
+                send_countdown_tick(&timers, &config, &sender);
+            }
+        }
+
+        state.send_replace(timer_state(&timers, &config));
+
+        if changed {
+            save_store(&timers, &parked, &store_path);
+        }
+    }
+}
+
+/// Ignores timer commands until a configuration arrives. `None` if the handle was dropped.
+async fn wait_for_config(commands: &mut mpsc::UnboundedReceiver<TimerCommand>) -> Option<Config> {
+    log::info!("Timers are not loaded until the configuration is valid");
+    loop {
+        match commands.recv().await? {
+            TimerCommand::UpdateConfig(config) => return Some(*config),
+            command => log::warn!("Ignoring {:?}, the configuration is invalid", command),
+        }
+    }
+}
+
+fn select_timers<'a>(
+    timers: &'a mut [TimerService],
+    group_id: Option<&'a str>,
+) -> impl Iterator<Item = &'a mut TimerService> {
+    timers
+        .iter_mut()
+        .filter(move |timer| group_id.is_none_or(|id| timer.group_id == id))
+}
+
+/// Keeps the timers of the active profile's groups and creates the missing ones, restoring
+/// parked records. Timers of other groups are stopped and parked, so switching back to their
+/// profile keeps the content rotation; records of deleted groups are dropped.
+fn update_timers(
+    mut timers: Vec<TimerService>,
+    config: &Config,
+    parked: &mut Vec<GroupTimerRecord>,
+) -> Vec<TimerService> {
+    let updated = config
+        .groups()
+        .iter()
+        .map(
+            |group| match timers.iter().position(|timer| timer.group_id == group.id) {
+                Some(index) => {
+                    let mut timer = timers.swap_remove(index);
+                    timer.update_config(group);
+                    timer
+                }
+                None => {
+                    let mut timer = TimerService::new(group);
+                    if let Some(index) = parked.iter().position(|r| r.group_id == group.id) {
+                        timer.restore(&parked.swap_remove(index), config.app.missed_toast_policy);
+                    }
+                    timer
+                }
+            },
+        )
+        .collect();
+
+    for mut timer in timers {
+        if timer.is_running {
+            timer.stop();
+        }
+        parked.push(timer.record());
+    }
+    parked.retain(|record| config.all_groups().any(|group| group.id == record.group_id));
+
+    updated
+}
+
+fn save_store(timers: &[TimerService], parked: &[GroupTimerRecord], store_path: &Path) {
+    let store = TimerStore {
+        groups: timers
+            .iter()
+            .map(TimerService::record)
+            .chain(parked.iter().cloned())
+            .collect(),
+    };
+
+    if let Err(e) = store.save(store_path) {
+        log::error!("Failed to save timer state: {}", e);
+    }
+}
+
+fn timer_state(timers: &[TimerService], config: &Config) -> TimerState {
+    TimerState {
+        groups: timers
+            .iter()
+            .zip(config.groups())
+            .map(|(timer, group)| timer.state(group))
+            .collect(),
+    }
+}
+
+/// Lets overlays show a countdown; sent on every wake-up while a timer is running.
+fn send_countdown_tick(
+    timers: &[TimerService],
+    config: &Config,
+    sender: &broadcast::Sender<WebSocketMessage>,
+) {
+    let groups: Vec<CountdownEntry> = timer_state(timers, config)
+        .groups
+        .iter()
+        .filter_map(|group| {
+            Some(CountdownEntry {
+                group_id: group.group_id.clone(),
+                group_name: group.group_name.clone(),
+                seconds_left: group.time_until_next_toast()?.as_secs(),
+            })
+        })
+        .collect();
+
+    // No receivers just means no overlay is connected
+    let _ = sender.send(WebSocketMessage::CountdownTick { groups });
+}
+
+async fn sleep_until(deadline: Option<DateTime<Local>>) {
+    match deadline {
+        Some(deadline) => {
+            let remaining = (deadline - Local::now()).to_std().unwrap_or_default();
+            tokio::time::sleep(remaining.min(MAX_SLEEP)).await
+        }
+        None => std::future::pending().await,
+    }
+}
+
+fn send_automatic_toast(timer: &mut TimerService, group: &ToasterConfig, queue: &ToastQueue) {
+    let Some(reminder) = timer.get_next_content(&group.reminders) else {
+        log::warn!("Cannot send automatic toast: no enabled reminders configured");
+        return;
+    };
+
+    // Placeholders are filled in before formatting, so their values are escaped like any text
+    let context = timer.template_context();
+    let mut rng = rand::thread_rng();
+    let title = template::expand(&reminder.title, &context, &mut rng);
+    let content = template::expand(&reminder.content, &context, &mut rng);
+
+    log::info!(
+        "Sending automatic toast for group {} - Title: {}, Content: {}",
+        group.name,
+        title,
+        content
+    );
+
+    // The queue logs whether the toast is shown, queued or dropped
+    queue.push(ToastMessage::from_config(group, title, content));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::toast_queue::QueueSettings;
+
+    #[tokio::test]
+    async fn timers_wait_for_a_valid_configuration() {
+        let store_path =
+            std::env::temp_dir().join(format!("obs-reminder-{}.state.toml", uuid::Uuid::new_v4()));
+        let mut config = Config::default();
+        config.profiles[0].groups[0].id = "stream".to_string();
+        let record = GroupTimerRecord {
+            group_id: "stream".to_string(),
+            is_running: true,
+            started_at: None,
+            last_toast_time: None,
+            next_toast_time: None,
+            selector: ContentSelector::default(),
+        };
+        TimerStore {
+            groups: vec![record],
+        }
+        .save(&store_path)
+        .unwrap();
+        let saved = std::fs::read_to_string(&store_path).unwrap();
+
+        let (sender, _) = broadcast::channel(16);
+        let queue = ToastQueue::spawn(sender.clone(), QueueSettings::from_config(&config.app));
+        let timer = TimerHandle::spawn(None, sender, queue, store_path.clone());
+
+        // Commands are ignored and the records of the real groups are kept
+        timer.start(None);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(timer.state().groups.is_empty());
+        assert_eq!(std::fs::read_to_string(&store_path).unwrap(), saved);
+
+        timer.update_config(&config);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let state = timer.state();
+        std::fs::remove_file(&store_path).unwrap();
+        assert_eq!(state.groups.len(), 1);
+        assert!(state.groups[0].is_running);
     }
 }
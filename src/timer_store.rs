@@ -0,0 +1,106 @@
+use crate::selection::ContentSelector;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Appended to the stem of the configuration file, e.g. `config.state.toml`.
+const STATE_FILE_SUFFIX: &str = "state.toml";
+
+/// Where versions before per-config state files kept the state of `config.toml`.
+const LEGACY_STATE_FILE_NAME: &str = "state.toml";
+
+/// Timer state that survives restarts, stored next to the configuration file.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TimerStore {
+    #[serde(default)]
+    pub groups: Vec<GroupTimerRecord>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupTimerRecord {
+    pub group_id: String,
+    pub is_running: bool,
+    #[serde(default)]
+    pub started_at: Option<DateTime<Local>>,
+    pub last_toast_time: Option<DateTime<Local>>,
+    pub next_toast_time: Option<DateTime<Local>>,
+    #[serde(default)]
+    pub selector: ContentSelector,
+}
+
+impl TimerStore {
+    /// The state file lives next to the configuration file and is named after it, so several
+    /// configurations in one directory keep their own state.
+    pub fn path_for_config<P: AsRef<Path>>(config_path: P) -> PathBuf {
+        let config_path = config_path.as_ref();
+        let stem = config_path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "config".to_string());
+        let path = config_path.with_file_name(format!("{}.{}", stem, STATE_FILE_SUFFIX));
+
+        // Only the default configuration can have used the old shared name
+        let legacy_path = config_path.with_file_name(LEGACY_STATE_FILE_NAME);
+        if stem == "config" && !path.exists() && legacy_path.exists() {
+            match fs::rename(&legacy_path, &path) {
+                Ok(()) => log::info!(
+                    "Timer state moved from {} to {}",
+                    legacy_path.display(),
+                    path.display()
+                ),
+                Err(e) => log::warn!("Failed to move {}: {}", legacy_path.display(), e),
+            }
+        }
+        path
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Self {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Self::default();
+        }
+
+        match fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|content| toml::from_str(&content).map_err(|e| e.to_string()))
+        {
+            Ok(store) => {
+                log::info!("Timer state restored from {}", path.display());
+                store
+            }
+            Err(e) => {
+                log::warn!("Ignoring unreadable timer state {}: {}", path.display(), e);
+                Self::default()
+            }
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn std::error::Error>> {
+        let path = path.as_ref();
+        let toml_string = toml::to_string_pretty(self)?;
+
+        // Write to a temporary file first so a crash never leaves a half-written state
+        let tmp_path = path.with_extension("toml.tmp");
+        fs::write(&tmp_path, toml_string)?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_configuration_has_its_own_state_file() {
+        assert_eq!(
+            TimerStore::path_for_config("streams/a.toml"),
+            Path::new("streams/a.state.toml")
+        );
+        assert_eq!(
+            TimerStore::path_for_config("streams/b.toml"),
+            Path::new("streams/b.state.toml")
+        );
+    }
+}
@@ -0,0 +1,468 @@
+use crate::config::{AppConfig, OverlapPolicy, Priority};
+use crate::websocket::{ToastMessage, WebSocketMessage};
+use serde::Serialize;
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc, oneshot, watch};
+use tokio::time::Instant;
+
+/// Time added to a toast's duration for the overlay's intro and outro animations.
+const ANIMATION_GRACE: Duration = Duration::from_secs(1);
+
+/// Waiting toasts beyond this are dropped, lowest priority first.
+const MAX_PENDING: usize = 20;
+
+/// What happened to a toast handed to the queue.
+#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum QueueOutcome {
+    Shown,
+    Queued,
+    /// Shown after dismissing a toast that was on screen
+    Replaced,
+    Dropped,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QueueSettings {
+    pub policy: OverlapPolicy,
+    pub max_visible: usize,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct QueueState {
+    pub visible: usize,
+    pub pending: usize,
+}
+
+#[derive(Debug)]
+enum QueueCommand {
+    Push(Box<ToastMessage>, Option<oneshot::Sender<QueueOutcome>>),
+    /// An overlay took the toast off screen
+    Finished(String),
+    UpdateSettings(QueueSettings),
+}
+
+struct VisibleToast {
+    id: String,
+    priority: Priority,
+    channels: Vec<String>,
+    until: Instant,
+}
+
+/// Decides when toasts from the timer, the API, the window and controller clients reach the
+/// overlays, so they follow the configured overlap policy instead of piling up.
+#[derive(Clone)]
+pub struct ToastQueue {
+    commands: mpsc::UnboundedSender<QueueCommand>,
+    state: watch::Receiver<QueueState>,
+}
+
+impl QueueSettings {
+    pub fn from_config(app: &AppConfig) -> Self {
+        Self {
+            policy: app.overlap_policy,
+            max_visible: app.max_visible_toasts.max(1) as usize,
+        }
+    }
+
+    /// Toasts that may share the screen.
+    fn limit(&self) -> usize {
+        match self.policy {
+            OverlapPolicy::Stack => self.max_visible,
+            _ => 1,
+        }
+    }
+}
+
+impl ToastQueue {
+    pub fn spawn(sender: broadcast::Sender<WebSocketMessage>, settings: QueueSettings) -> Self {
+        let (commands, receiver) = mpsc::unbounded_channel();
+        let (state_sender, state) = watch::channel(QueueState::default());
+        let dismissals = sender.subscribe();
+
+        tokio::spawn(run_queue(
+            settings,
+            sender,
+            receiver,
+            dismissals,
+            state_sender,
+        ));
+
+        Self { commands, state }
+    }
+
+    /// Hands a toast to the queue, which logs whether it was shown, queued or dropped.
+    pub fn push(&self, toast: ToastMessage) {
+        self.send(QueueCommand::Push(Box::new(toast), None));
+    }
+
+    /// Like [`ToastQueue::push`], with a receiver for the outcome.
+    pub fn push_with_outcome(&self, toast: ToastMessage) -> oneshot::Receiver<QueueOutcome> {
+        let (outcome_sender, outcome) = oneshot::channel();
+        self.send(QueueCommand::Push(Box::new(toast), Some(outcome_sender)));
+        outcome
+    }
+
+    pub fn finished(&self, id: &str) {
+        self.send(QueueCommand::Finished(id.to_string()));
+    }
+
+    pub fn update_settings(&self, settings: QueueSettings) {
+        self.send(QueueCommand::UpdateSettings(settings));
+    }
+
+    pub fn state(&self) -> QueueState {
+        *self.state.borrow()
+    }
+
+    fn send(&self, command: QueueCommand) {
+        if self.commands.send(command).is_err() {
+            log::error!("Toast queue is not running");
+        }
+    }
+}
+
+struct Queue {
+    settings: QueueSettings,
+    sender: broadcast::Sender<WebSocketMessage>,
+    visible: Vec<VisibleToast>,
+    pending: Vec<ToastMessage>, // highest priority first, in arrival order otherwise
+}
+
+async fn run_queue(
+    settings: QueueSettings,
+    sender: broadcast::Sender<WebSocketMessage>,
+    mut commands: mpsc::UnboundedReceiver<QueueCommand>,
+    mut dismissals: broadcast::Receiver<WebSocketMessage>,
+    state: watch::Sender<QueueState>,
+) {
+    let mut queue = Queue {
+        settings,
+        sender,
+        visible: Vec::new(),
+        pending: Vec::new(),
+    };
+
+    loop {
+        let next_expiry = queue.visible.iter().map(|toast| toast.until).min();
+
+        tokio::select! {
+            command = commands.recv() => match command {
+                Some(QueueCommand::Push(toast, outcome_sender)) => {
+                    let outcome = queue.push(*toast);
+                    if let Some(outcome_sender) = outcome_sender {
+                        let _ = outcome_sender.send(outcome);
+                    }
+                }
+                Some(QueueCommand::Finished(id)) => queue.remove(&id),
+                Some(QueueCommand::UpdateSettings(settings)) => queue.settings = settings,
+                None => {
+                    log::debug!("Toast queue command channel closed, stopping queue task");
+                    break;
+                }
+            },
+            // Dismissals from the API take toasts off screen as well
+            message = dismissals.recv() => match message {
+                Ok(WebSocketMessage::Dismiss { id }) => queue.remove(&id),
+                Ok(WebSocketMessage::ClearAll) => {
+                    queue.visible.clear();
+                    queue.pending.clear();
+                }
+                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            _ = sleep_until(next_expiry) => {
+                let now = Instant::now();
+                queue.visible.retain(|toast| toast.until > now);
+            }
+        }
+
+        queue.show_pending();
+        state.send_replace(QueueState {
+            visible: queue.visible.len(),
+            pending: queue.pending.len(),
+        });
+    }
+}
+
+impl Queue {
+    fn push(&mut self, toast: ToastMessage) -> QueueOutcome {
+        let blocking: Vec<usize> = (0..self.visible.len())
+            .filter(|&i| overlaps(&self.visible[i].channels, &toast.channels))
+            .collect();
+
+        if blocking.len() < self.settings.limit() {
+            self.show(toast);
+            return QueueOutcome::Shown;
+        }
+
+        // The lowest priority toast on screen, oldest first
+        let weakest = blocking
+            .iter()
+            .copied()
+            .min_by_key(|&i| self.visible[i].priority)
+            .unwrap_or_default();
+        let weakest_priority = self.visible[weakest].priority;
+
+        let replace = match self.settings.policy {
+            OverlapPolicy::Stack | OverlapPolicy::Queue => false,
+            OverlapPolicy::Replace => toast.priority >= weakest_priority,
+            OverlapPolicy::Drop => toast.priority > weakest_priority,
+        };
+
+        if replace {
+            let replaced = self.visible.remove(weakest);
+            log::info!("Toast {} replaced by {}", replaced.id, toast.id);
+            let _ = self
+                .sender
+                .send(WebSocketMessage::Dismiss { id: replaced.id });
+            self.show(toast);
+            QueueOutcome::Replaced
+        } else if self.settings.policy == OverlapPolicy::Drop {
+            log::info!(
+                "Toast {} dropped while another toast is on screen",
+                toast.id
+            );
+            QueueOutcome::Dropped
+        } else {
+            self.enqueue(toast)
+        }
+    }
+
+    fn enqueue(&mut self, toast: ToastMessage) -> QueueOutcome {
+        let position = self
+            .pending
+            .iter()
+            .position(|queued| queued.priority < toast.priority)
+            .unwrap_or(self.pending.len());
+        let id = toast.id.clone();
+        self.pending.insert(position, toast);
+
+        if self.pending.len() > MAX_PENDING {
+            let dropped = self.pending.pop().map(|toast| toast.id).unwrap_or_default();
+            log::warn!("Toast queue is full, dropping toast {}", dropped);
+            if dropped == id {
+                return QueueOutcome::Dropped;
+            }
+        }
+
+        log::info!("Toast {} queued at position {}", id, position + 1);
+        QueueOutcome::Queued
+    }
+
+    /// Shows waiting toasts in order as long as they fit next to the ones on screen.
+    fn show_pending(&mut self) {
+        let mut i = 0;
+        while i < self.pending.len() {
+            let blocking = self
+                .visible
+                .iter()
+                .filter(|visible| overlaps(&visible.channels, &self.pending[i].channels))
+                .count();
+
+            if blocking < self.settings.limit() {
+                let toast = self.pending.remove(i);
+                self.show(toast);
+            } else {
+                i += 1;
+            }
+        }
+    }
+
+    fn show(&mut self, toast: ToastMessage) {
+        log::info!("Showing toast {}", toast.id);
+        self.visible.push(VisibleToast {
+            id: toast.id.clone(),
+            priority: toast.priority,
+            channels: toast.channels.clone(),
+            until: Instant::now() + Duration::from_millis(toast.duration as u64) + ANIMATION_GRACE,
+        });
+
+        // No receivers just means no overlay is connected
+        let _ = self.sender.send(WebSocketMessage::Toast(Box::new(toast)));
+    }
+
+    fn remove(&mut self, id: &str) {
+        self.visible.retain(|toast| toast.id != id);
+        self.pending.retain(|toast| toast.id != id);
+    }
+}
+
+/// Toasts only compete for space on overlays that show both; no channels means every overlay.
+fn overlaps(a: &[String], b: &[String]) -> bool {
+    a.is_empty() || b.is_empty() || a.iter().any(|channel| b.contains(channel))
+}
+
+async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ToasterConfig;
+
+    fn queue(
+        policy: OverlapPolicy,
+        max_visible: usize,
+    ) -> (Queue, broadcast::Receiver<WebSocketMessage>) {
+        let (sender, receiver) = broadcast::channel(64);
+        let queue = Queue {
+            settings: QueueSettings {
+                policy,
+                max_visible,
+            },
+            sender,
+            visible: Vec::new(),
+            pending: Vec::new(),
+        };
+        (queue, receiver)
+    }
+
+    fn toast(priority: Priority, channels: &[&str]) -> ToastMessage {
+        let config = ToasterConfig {
+            priority,
+            channels: channels.iter().map(|c| c.to_string()).collect(),
+            ..Default::default()
+        };
+        ToastMessage::from_config(&config, "Title".to_string(), "Content".to_string())
+    }
+
+    fn visible_ids(queue: &Queue) -> Vec<&str> {
+        queue
+            .visible
+            .iter()
+            .map(|toast| toast.id.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn stack_shows_up_to_max_visible() {
+        let (mut queue, _receiver) = queue(OverlapPolicy::Stack, 2);
+        let first = toast(Priority::Normal, &[]);
+        let first_id = first.id.clone();
+        assert_eq!(queue.push(first), QueueOutcome::Shown);
+        assert_eq!(
+            queue.push(toast(Priority::Normal, &[])),
+            QueueOutcome::Shown
+        );
+        // Priority does not matter when stacking
+        assert_eq!(queue.push(toast(Priority::High, &[])), QueueOutcome::Queued);
+
+        queue.remove(&first_id);
+        queue.show_pending();
+        assert_eq!(queue.visible.len(), 2);
+        assert!(queue.pending.is_empty());
+    }
+
+    #[test]
+    fn queue_waits_only_for_overlapping_channels() {
+        let (mut queue, _receiver) = queue(OverlapPolicy::Queue, 3);
+        assert_eq!(
+            queue.push(toast(Priority::Low, &["a"])),
+            QueueOutcome::Shown
+        );
+        assert_eq!(
+            queue.push(toast(Priority::High, &["a"])),
+            QueueOutcome::Queued
+        );
+        assert_eq!(
+            queue.push(toast(Priority::Normal, &["b"])),
+            QueueOutcome::Shown
+        );
+        // No channels means every overlay, so it waits for both
+        assert_eq!(
+            queue.push(toast(Priority::Normal, &[])),
+            QueueOutcome::Queued
+        );
+        assert_eq!(queue.pending.len(), 2);
+    }
+
+    #[test]
+    fn replace_dismisses_the_toast_on_screen() {
+        let (mut queue, mut receiver) = queue(OverlapPolicy::Replace, 1);
+        let first = toast(Priority::Normal, &[]);
+        let first_id = first.id.clone();
+        queue.push(first);
+        let second = toast(Priority::Normal, &[]);
+        let second_id = second.id.clone();
+
+        assert_eq!(queue.push(second), QueueOutcome::Replaced);
+        assert_eq!(visible_ids(&queue), [second_id.as_str()]);
+        let messages: Vec<WebSocketMessage> =
+            std::iter::from_fn(|| receiver.try_recv().ok()).collect();
+        assert!(
+            messages
+                .iter()
+                .any(|m| matches!(m, WebSocketMessage::Dismiss { id } if *id == first_id))
+        );
+
+        // Lower priorities wait instead
+        assert_eq!(queue.push(toast(Priority::Low, &[])), QueueOutcome::Queued);
+        assert_eq!(visible_ids(&queue), [second_id.as_str()]);
+    }
+
+    #[test]
+    fn drop_discards_unless_more_important() {
+        let (mut queue, _receiver) = queue(OverlapPolicy::Drop, 1);
+        queue.push(toast(Priority::Normal, &[]));
+        assert_eq!(
+            queue.push(toast(Priority::Normal, &[])),
+            QueueOutcome::Dropped
+        );
+        assert_eq!(queue.push(toast(Priority::Low, &[])), QueueOutcome::Dropped);
+        assert_eq!(
+            queue.push(toast(Priority::High, &[])),
+            QueueOutcome::Replaced
+        );
+        assert!(queue.pending.is_empty());
+    }
+
+    #[test]
+    fn pending_toasts_are_ordered_by_priority() {
+        let (mut queue, _receiver) = queue(OverlapPolicy::Queue, 1);
+        let shown = toast(Priority::Normal, &[]);
+        let shown_id = shown.id.clone();
+        queue.push(shown);
+
+        let waiting = [
+            toast(Priority::Low, &[]),
+            toast(Priority::Normal, &[]),
+            toast(Priority::High, &[]),
+            toast(Priority::Normal, &[]),
+        ];
+        let ids: Vec<String> = waiting.iter().map(|toast| toast.id.clone()).collect();
+        for toast in waiting {
+            assert_eq!(queue.push(toast), QueueOutcome::Queued);
+        }
+        let pending: Vec<&str> = queue.pending.iter().map(|t| t.id.as_str()).collect();
+        assert_eq!(pending, [&ids[2], &ids[1], &ids[3], &ids[0]]);
+
+        queue.remove(&shown_id);
+        queue.show_pending();
+        assert_eq!(visible_ids(&queue), [ids[2].as_str()]);
+    }
+
+    #[test]
+    fn full_queue_drops_the_least_important() {
+        let (mut queue, _receiver) = queue(OverlapPolicy::Queue, 1);
+        queue.push(toast(Priority::Normal, &[]));
+        for _ in 0..MAX_PENDING {
+            assert_eq!(queue.push(toast(Priority::Low, &[])), QueueOutcome::Queued);
+        }
+        let last_low = queue.pending[MAX_PENDING - 1].id.clone();
+
+        // A more important toast pushes out the newest low one
+        assert_eq!(queue.push(toast(Priority::High, &[])), QueueOutcome::Queued);
+        assert_eq!(queue.pending.len(), MAX_PENDING);
+        assert_eq!(queue.pending[0].priority, Priority::High);
+        assert!(queue.pending.iter().all(|toast| toast.id != last_low));
+
+        // Another low one has no room
+        assert_eq!(queue.push(toast(Priority::Low, &[])), QueueOutcome::Dropped);
+        assert_eq!(queue.pending.len(), MAX_PENDING);
+    }
+}
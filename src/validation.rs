@@ -0,0 +1,391 @@
+use crate::config::{
+    Config, MAX_VISIBLE_TOASTS, ServerConfig, ToasterConfig, is_valid_channel_name,
+    is_valid_hex_color,
+};
+use crate::template;
+use std::fmt;
+
+/// Texts longer than this still work but rarely fit on a toast.
+const LONG_TITLE: usize = 40;
+const LONG_CONTENT: usize = 150;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,   // the configuration cannot be saved or run
+    Warning, // works, but probably not as intended
+}
+
+/// What is wrong with a field.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IssueKind {
+    Empty,
+    OutOfRange { min: u32, max: u32 },
+    InvalidColor,
+    InvalidChannel,
+    InvalidTime,
+    Duplicate,
+    UnknownProfile,
+    NothingEnabled,
+    SameAs(&'static str), // must differ from this sibling field
+    Template(String),
+    UnknownVariable(String), // shown as written, probably a typo
+    TooLong { length: usize, max: usize },
+    NoSoundFile,
+}
+
+/// One problem, located by its path in `config.toml`, e.g.
+/// `profiles[0].groups[1].reminders[3].title`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigIssue {
+    pub path: String,
+    pub kind: IssueKind,
+}
+
+/// Every issue of a configuration, in the order of the file.
+#[derive(Debug, Clone, Default)]
+pub struct Validation {
+    pub issues: Vec<ConfigIssue>,
+}
+
+/// The errors that keep a configuration from being used.
+#[derive(Debug, Clone)]
+pub struct ValidationErrors(pub Vec<ConfigIssue>);
+
+impl IssueKind {
+    pub fn severity(&self) -> Severity {
+        match self {
+            IssueKind::UnknownVariable(_) | IssueKind::TooLong { .. } | IssueKind::NoSoundFile => {
+                Severity::Warning
+            }
+            _ => Severity::Error,
+        }
+    }
+}
+
+impl fmt::Display for IssueKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IssueKind::Empty => write!(f, "empty"),
+            IssueKind::OutOfRange { min, max } => write!(f, "must be between {} and {}", min, max),
+            IssueKind::InvalidColor => write!(f, "not a hex color like #FF6B6B"),
+            IssueKind::InvalidChannel => write!(f, "use letters, digits, '-' and '_'"),
+            IssueKind::InvalidTime => write!(f, "not a time like 21:00"),
+            IssueKind::Duplicate => write!(f, "used more than once"),
+            IssueKind::UnknownProfile => write!(f, "no profile has this name"),
+            IssueKind::NothingEnabled => write!(f, "no reminder is enabled"),
+            IssueKind::SameAs(field) => write!(f, "must differ from {}", field),
+            IssueKind::Template(error) => write!(f, "{}", error),
+            IssueKind::UnknownVariable(name) => write!(
+                f,
+                "unknown variable {{{}}}, expected one of {}",
+                name,
+                template::VARIABLES.map(|v| format!("{{{}}}", v)).join(", ")
+            ),
+            IssueKind::TooLong { length, max } => {
+                write!(f, "{} characters, toasts fit about {}", length, max)
+            }
+            IssueKind::NoSoundFile => write!(f, "sound is enabled but no file is chosen"),
+        }
+    }
+}
+
+impl ConfigIssue {
+    pub fn severity(&self) -> Severity {
+        self.kind.severity()
+    }
+}
+
+impl fmt::Display for ConfigIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.kind)
+    }
+}
+
+impl Validation {
+    pub fn push(&mut self, path: impl Into<String>, kind: IssueKind) {
+        self.issues.push(ConfigIssue {
+            path: path.into(),
+            kind,
+        });
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &ConfigIssue> {
+        self.issues
+            .iter()
+            .filter(|issue| issue.severity() == Severity::Error)
+    }
+
+    pub fn warnings(&self) -> impl Iterator<Item = &ConfigIssue> {
+        self.issues
+            .iter()
+            .filter(|issue| issue.severity() == Severity::Warning)
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.errors().next().is_some()
+    }
+
+    /// Issues of exactly this field.
+    pub fn field<'a>(&'a self, path: &'a str) -> impl Iterator<Item = &'a ConfigIssue> {
+        self.issues.iter().filter(move |issue| issue.path == path)
+    }
+
+    /// Issues of a field and everything inside it.
+    pub fn within<'a>(&'a self, path: &'a str) -> impl Iterator<Item = &'a ConfigIssue> {
+        self.issues.iter().filter(move |issue| {
+            issue
+                .path
+                .strip_prefix(path)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with(['.', '[']))
+        })
+    }
+}
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let errors: Vec<String> = self.0.iter().map(ToString::to_string).collect();
+        write!(f, "{}", errors.join("; "))
+    }
+}
+
+impl std::error::Error for ValidationErrors {}
+
+impl Config {
+    /// Checks every field and reports all errors and warnings.
+    pub fn check(&self) -> Validation {
+        let mut validation = Validation::default();
+
+        if self.profiles.is_empty() {
+            validation.push("profiles", IssueKind::Empty);
+        }
+
+        self.server.check(&mut validation);
+
+        if self.app.max_visible_toasts == 0 || self.app.max_visible_toasts > MAX_VISIBLE_TOASTS {
+            validation.push(
+                "app.max_visible_toasts",
+                IssueKind::OutOfRange {
+                    min: 1,
+                    max: MAX_VISIBLE_TOASTS,
+                },
+            );
+        }
+
+        if !self.active_profile.is_empty() && self.profile(&self.active_profile).is_none() {
+            validation.push("active_profile", IssueKind::UnknownProfile);
+        }
+
+        // Ids are unique across profiles since the timer state is stored by group id
+        let mut group_ids: Vec<&str> = Vec::new();
+        for (i, profile) in self.profiles.iter().enumerate() {
+            let path = format!("profiles[{}]", i);
+            if profile.name.trim().is_empty() {
+                validation.push(format!("{}.name", path), IssueKind::Empty);
+            } else if self.profiles[..i].iter().any(|p| p.name == profile.name) {
+                validation.push(format!("{}.name", path), IssueKind::Duplicate);
+            }
+            if profile.groups.is_empty() {
+                validation.push(format!("{}.groups", path), IssueKind::Empty);
+            }
+
+            for (j, group) in profile.groups.iter().enumerate() {
+                let path = format!("{}.groups[{}]", path, j);
+                if group_ids.contains(&group.id.as_str()) {
+                    validation.push(format!("{}.id", path), IssueKind::Duplicate);
+                }
+                group_ids.push(&group.id);
+                group.check(&path, &mut validation);
+            }
+        }
+
+        validation
+    }
+
+    /// Returns the warnings if the configuration can be used, or every error if not.
+    pub fn validate(&self) -> Result<Vec<ConfigIssue>, ValidationErrors> {
+        let validation = self.check();
+        if validation.has_errors() {
+            return Err(ValidationErrors(validation.errors().cloned().collect()));
+        }
+        Ok(validation.issues)
+    }
+}
+
+impl ServerConfig {
+    fn check(&self, validation: &mut Validation) {
+        if self.host.trim().is_empty() {
+            validation.push("server.host", IssueKind::Empty);
+        }
+
+        if self.websocket_port == Some(self.http_port) && self.http_port != 0 {
+            validation.push("server.websocket_port", IssueKind::SameAs("http_port"));
+        }
+    }
+}
+
+impl ToasterConfig {
+    fn check(&self, path: &str, validation: &mut Validation) {
+        // There must be something to show
+        if !self.reminders.iter().any(|r| r.enabled) {
+            validation.push(format!("{}.reminders", path), IssueKind::NothingEnabled);
+        }
+
+        for (i, reminder) in self.reminders.iter().enumerate() {
+            let path = format!("{}.reminders[{}]", path, i);
+            if reminder.title.trim().is_empty() && reminder.content.trim().is_empty() {
+                validation.push(path.clone(), IssueKind::Empty);
+            }
+
+            for (field, text, max) in [
+                ("title", &reminder.title, LONG_TITLE),
+                ("content", &reminder.content, LONG_CONTENT),
+            ] {
+                let path = format!("{}.{}", path, field);
+                if let Err(e) = template::validate(text) {
+                    validation.push(path.clone(), IssueKind::Template(e));
+                }
+                for name in template::unknown_variables(text) {
+                    validation.push(path.clone(), IssueKind::UnknownVariable(name.to_string()));
+                }
+                let length = text.chars().count();
+                if length > max {
+                    validation.push(path, IssueKind::TooLong { length, max });
+                }
+            }
+
+            if reminder.weight == 0 || reminder.weight > 1000 {
+                validation.push(
+                    format!("{}.weight", path),
+                    IssueKind::OutOfRange { min: 1, max: 1000 },
+                );
+            }
+        }
+
+        // 1-1440 minutes = 1 day
+        if self.interval_time == 0 || self.interval_time > 1440 {
+            validation.push(
+                format!("{}.interval_time", path),
+                IssueKind::OutOfRange { min: 1, max: 1440 },
+            );
+        }
+
+        self.schedule
+            .check(&format!("{}.schedule", path), validation);
+
+        if self.duration == 0 || self.duration > 60 {
+            validation.push(
+                format!("{}.duration", path),
+                IssueKind::OutOfRange { min: 1, max: 60 },
+            );
+        }
+
+        for (field, color) in [
+            ("color_1", &self.color_1),
+            ("color_2", &self.color_2),
+            ("text_color", &self.text_color),
+        ] {
+            if !is_valid_hex_color(color) {
+                validation.push(format!("{}.{}", path, field), IssueKind::InvalidColor);
+            }
+        }
+
+        for (i, channel) in self.channels.iter().enumerate() {
+            if !is_valid_channel_name(channel) {
+                validation.push(
+                    format!("{}.channels[{}]", path, i),
+                    IssueKind::InvalidChannel,
+                );
+            }
+        }
+
+        if self.enable_sound && self.sound_file_id.is_none() {
+            validation.push(format!("{}.sound_file_id", path), IssueKind::NoSoundFile);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Reminder;
+
+    #[test]
+    fn every_error_is_reported_with_its_path() {
+        let mut config = Config::default();
+        let group = &mut config.profiles[0].groups[0];
+        group.reminders.push(Reminder::new("", " "));
+        group.duration = 0;
+        group.color_2 = "teal".to_string();
+
+        let errors = config.validate().unwrap_err();
+        let paths: Vec<&str> = errors.0.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            [
+                "profiles[0].groups[0].reminders[2]",
+                "profiles[0].groups[0].duration",
+                "profiles[0].groups[0].color_2",
+            ]
+        );
+        assert_eq!(
+            errors.0[0].to_string(),
+            "profiles[0].groups[0].reminders[2]: empty"
+        );
+    }
+
+    #[test]
+    fn warnings_do_not_block() {
+        let mut config = Config::default();
+        let group = &mut config.profiles[0].groups[0];
+        group.reminders[0].title = "这个标题长到在提示框里一行放不下，".repeat(3);
+        group.enable_sound = true;
+
+        let warnings = config.validate().unwrap();
+        assert!(matches!(
+            warnings[0].kind,
+            IssueKind::TooLong {
+                length: 51,
+                max: LONG_TITLE
+            }
+        ));
+        assert_eq!(warnings[1].kind, IssueKind::NoSoundFile);
+    }
+
+    #[test]
+    fn unknown_variables_are_reported() {
+        let mut config = Config::default();
+        let group = &mut config.profiles[0].groups[0];
+        group.reminders[0].title = "{nmae} 你好".to_string();
+        group.reminders[0].content = "{ not a var } {\"a\": 1} {tme:x}".to_string();
+
+        let warnings = config.validate().unwrap();
+        let issues: Vec<(&str, &IssueKind)> = warnings
+            .iter()
+            .map(|issue| (issue.path.as_str(), &issue.kind))
+            .collect();
+        assert_eq!(
+            issues,
+            [
+                (
+                    "profiles[0].groups[0].reminders[0].title",
+                    &IssueKind::UnknownVariable("nmae".to_string())
+                ),
+                (
+                    "profiles[0].groups[0].reminders[0].content",
+                    &IssueKind::UnknownVariable("tme".to_string())
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn within_matches_whole_path_segments() {
+        let mut validation = Validation::default();
+        validation.push("profiles[0].groups[1].duration", IssueKind::Empty);
+        validation.push("profiles[0].groups[10].duration", IssueKind::Empty);
+
+        assert_eq!(validation.within("profiles[0].groups[1]").count(), 1);
+        assert_eq!(validation.within("profiles[0]").count(), 2);
+        assert_eq!(validation.field("profiles[0].groups[1]").count(), 0);
+    }
+}
@@ -1,17 +1,48 @@
+use crate::audio_manager::is_file_id;
+use crate::config::{
+    Priority, ToastDirection, ToasterConfig, is_valid_channel_name, is_valid_hex_color,
+};
+use crate::markup::{TextFormat, to_html, to_plain};
+use crate::overlays::{OverlayInfo, OverlayRegistry};
+use crate::toast_queue::{QueueSettings, ToastQueue};
 use futures_util::{SinkExt, StreamExt};
+use hyper::{HeaderMap, StatusCode};
 use serde::{Deserialize, Serialize};
-use std::net::SocketAddr;
 use std::sync::Arc;
-use tokio::net::{TcpListener, TcpStream};
-use tokio::sync::{Mutex, broadcast};
-use tokio_tungstenite::{accept_async, tungstenite::Message};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
+use tokio::sync::{broadcast, oneshot};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::{WebSocketStream, accept_hdr_async};
+use uuid::Uuid;
 
-pub const WEBSOCKET_PORT: u16 = 7981;
+/// Version of the overlay protocol. Overlays from before the handshake existed count as 1.
+pub const PROTOCOL_VERSION: u32 = 2;
 
+/// How long a client has to send its hello before it is treated as an outdated overlay.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(3);
+
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+
+/// A toast as sent to overlays. The `_html` and `_plain` fields are always produced by
+/// [`ToastMessage::sanitize`] on the server; overlays must never render `title`/`content` as HTML.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ToastMessage {
+    pub id: String,
     pub title: String,
     pub content: String,
+    #[serde(default)]
+    pub format: TextFormat,
+    #[serde(default)]
+    pub title_html: String,
+    #[serde(default)]
+    pub content_html: String,
+    #[serde(default)]
+    pub title_plain: String, // the text without formatting, for overlays that cannot show HTML
+    #[serde(default)]
+    pub content_plain: String,
     pub color_1: String,
     pub color_2: String,
     pub text_color: String,
@@ -19,12 +50,187 @@ pub struct ToastMessage {
     pub play_sound: bool,
     pub sound_url: Option<String>,
     pub direction: String, // "top", "bottom", "left", "right"
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub channels: Vec<String>, // overlay channels to show on; empty means every overlay
+    #[serde(default)]
+    pub priority: Priority,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CountdownEntry {
+    pub group_id: String,
+    pub group_name: String,
+    pub seconds_left: u64,
+}
+
+/// Messages sent from the server to overlays.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum WebSocketMessage {
+    /// First message on every connection
+    Hello {
+        protocol_version: u32,
+        server_version: String,
+    },
+    Toast(Box<ToastMessage>),
+    /// Remove a toast that is still on screen
+    Dismiss {
+        id: String,
+    },
+    /// Change the text of a toast that is still on screen; the HTML is sanitized like a toast's
+    UpdateToast {
+        id: String,
+        title_html: Option<String>,
+        content_html: Option<String>,
+    },
+    ClearAll,
+    ConfigChanged,
+    CountdownTick {
+        groups: Vec<CountdownEntry>,
+    },
+    Ping {
+        timestamp: i64,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ClientRole {
+    /// A browser source that displays toasts
+    #[default]
+    Overlay,
+    /// A tool that only pushes messages, like `obs-reminder send`
+    Controller,
 }
 
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Viewport {
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Messages sent from clients to the server.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct WebSocketMessage {
-    pub r#type: String,
-    pub data: ToastMessage,
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum ClientMessage {
+    Hello {
+        protocol_version: u32,
+        #[serde(default)]
+        role: ClientRole,
+        #[serde(default)]
+        name: Option<String>,
+        #[serde(default)]
+        viewport: Option<Viewport>,
+        /// Only toasts for this channel (and toasts without channels) are sent to the overlay
+        #[serde(default)]
+        channel: Option<String>,
+    },
+    Pong {
+        timestamp: i64,
+    },
+    /// Toasts pushed by clients (e.g. `obs-reminder send`) are relayed to every overlay
+    Toast(Box<ToastMessage>),
+    /// The toast is on screen
+    Shown {
+        id: String,
+    },
+    /// The toast left the screen
+    Dismissed {
+        id: String,
+    },
+    /// The toast sound could not be played, usually because autoplay was blocked
+    AudioFailed {
+        id: String,
+        #[serde(default)]
+        reason: String,
+    },
+}
+
+impl ToastMessage {
+    pub fn from_config(config: &ToasterConfig, title: String, content: String) -> Self {
+        let sound_url = if config.enable_sound {
+            config.sound_file_id.as_ref().map(|id| {
+                let url = format!("/audio/{}", id);
+                log::info!("Generated sound URL: {} for ID: {}", url, id);
+                url
+            })
+        } else {
+            None
+        };
+
+        let mut toast = Self {
+            id: Uuid::new_v4().to_string(),
+            title,
+            content,
+            format: config.text_format,
+            title_html: String::new(),
+            content_html: String::new(),
+            title_plain: String::new(),
+            content_plain: String::new(),
+            color_1: config.color_1.clone(),
+            color_2: config.color_2.clone(),
+            text_color: config.text_color.clone(),
+            duration: config.duration * 1000,
+            play_sound: config.enable_sound,
+            sound_url,
+            direction: config.toast_direction.as_str().to_string(),
+            channels: config.channels.clone(),
+            priority: config.priority,
+        };
+        toast.sanitize();
+        toast
+    }
+
+    /// Rebuilds the HTML and plain fields from the text, so whatever a client sent in them is
+    /// discarded.
+    pub fn sanitize(&mut self) {
+        self.title_html = to_html(&self.title, self.format);
+        self.content_html = to_html(&self.content, self.format);
+        self.title_plain = to_plain(&self.title, self.format);
+        self.content_plain = to_plain(&self.content, self.format);
+    }
+
+    /// Checks the fields a client may have filled in itself, with the same rules as
+    /// `POST /api/toast`. Colors end up in CSS and the sound URL in `new Audio(...)`.
+    pub fn validate(&self) -> Result<(), String> {
+        for color in [&self.color_1, &self.color_2, &self.text_color] {
+            if !is_valid_hex_color(color) {
+                return Err(format!("Invalid hex color: {}", color));
+            }
+        }
+
+        if let Some(url) = &self.sound_url
+            && !url.strip_prefix("/audio/").is_some_and(is_file_id)
+        {
+            return Err(format!("Invalid sound URL: {}", url));
+        }
+
+        // Milliseconds on the wire
+        if !(1000..=60_000).contains(&self.duration) {
+            return Err("Toast duration must be between 1 and 60 seconds".to_string());
+        }
+
+        let directions = [
+            ToastDirection::Top,
+            ToastDirection::Bottom,
+            ToastDirection::Left,
+            ToastDirection::Right,
+        ];
+        if !directions.iter().any(|d| d.as_str() == self.direction) {
+            return Err(format!("Invalid toast direction: {}", self.direction));
+        }
+
+        if let Some(channel) = self.channels.iter().find(|c| !is_valid_channel_name(c)) {
+            return Err(format!("Invalid channel name: {}", channel));
+        }
+
+        Ok(())
+    }
+
+    /// Whether an overlay subscribed to `channel` should show this toast.
+    pub fn targets(&self, channel: Option<&str>) -> bool {
+        self.channels.is_empty() || channel.is_some_and(|c| self.channels.iter().any(|t| t == c))
+    }
 }
 
 impl WebSocketMessage {
@@ -40,22 +246,59 @@ impl WebSocketMessage {
         sound_url: Option<String>,
         direction: String,
     ) -> Self {
-        Self {
-            r#type: "toast".to_string(),
-            data: ToastMessage {
-                title,
-                content,
-                color_1,
-                color_2,
-                text_color,
-                duration: duration_seconds * 1000,
-                play_sound,
-                sound_url,
-                direction,
-            },
+        let mut toast = ToastMessage {
+            id: Uuid::new_v4().to_string(),
+            title,
+            content,
+            format: TextFormat::Plain,
+            title_html: String::new(),
+            content_html: String::new(),
+            title_plain: String::new(),
+            content_plain: String::new(),
+            color_1,
+            color_2,
+            text_color,
+            duration: duration_seconds * 1000,
+            play_sound,
+            sound_url,
+            direction,
+            channels: Vec::new(),
+            priority: Priority::Normal,
+        };
+        toast.sanitize();
+        Self::Toast(Box::new(toast))
+    }
+
+    fn hello() -> Self {
+        Self::Hello {
+            protocol_version: PROTOCOL_VERSION,
+            server_version: std::env!("CARGO_PKG_VERSION").to_string(),
         }
     }
 
+    /// A toast in the shape understood by overlays that predate the handshake, asking the
+    /// user to refresh the browser source.
+    fn outdated_overlay_notice() -> Self {
+        Self::new_toast(
+            "OBS Reminder".to_string(),
+            "浏览器源版本过旧，请刷新浏览器源".to_string(),
+            "#FF6B6B".to_string(),
+            "#4ECDC4".to_string(),
+            "#FFFFFF".to_string(),
+            15,
+            false,
+            None,
+            "top".to_string(),
+        )
+    }
+
+    pub fn to_message(&self) -> Result<Message, serde_json::Error> {
+        let json = serde_json::to_string(self)?;
+        Ok(Message::Text(json))
+    }
+}
+
+impl ClientMessage {
     pub fn to_message(&self) -> Result<Message, serde_json::Error> {
         let json = serde_json::to_string(self)?;
         Ok(Message::Text(json))
@@ -63,48 +306,86 @@ impl WebSocketMessage {
 }
 
 pub struct WebSocketServer {
-    addr: SocketAddr,
     sender: broadcast::Sender<WebSocketMessage>,
-    client_count: Arc<Mutex<usize>>,
+    overlays: Arc<OverlayRegistry>,
+    queue: ToastQueue,
 }
 
 impl WebSocketServer {
-    pub fn new() -> Self {
+    pub fn new(queue_settings: QueueSettings) -> Self {
         let (sender, _receiver) = broadcast::channel(100);
-        let addr = SocketAddr::from(([127, 0, 0, 1], WEBSOCKET_PORT));
+        let queue = ToastQueue::spawn(sender.clone(), queue_settings);
 
         Self {
-            addr,
             sender,
-            client_count: Arc::new(Mutex::new(0)),
+            overlays: Arc::new(OverlayRegistry::default()),
+            queue,
         }
     }
 
+    /// Toasts go through the queue; the sender is for everything else.
+    pub fn queue(&self) -> &ToastQueue {
+        &self.queue
+    }
+
     pub fn get_sender(&self) -> broadcast::Sender<WebSocketMessage> {
         self.sender.clone()
     }
 
-    pub async fn _get_client_count(&self) -> usize {
-        *self.client_count.lock().await
+    pub fn get_client_count(&self) -> usize {
+        self.overlays.connection_count()
     }
 
-    pub async fn start(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let listener = TcpListener::bind(&self.addr).await?;
-        log::info!("WebSocket server listening on {}", self.addr);
+    pub fn overlays(&self) -> &OverlayRegistry {
+        &self.overlays
+    }
+
+    /// Accepts connections on a separate listener; `authorize` checks the handshake headers
+    /// and query string.
+    pub async fn start<A>(
+        &self,
+        listener: TcpListener,
+        authorize: A,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>>
+    where
+        A: Fn(&HeaderMap, Option<&str>) -> bool + Clone + Send + Sync + 'static,
+    {
+        log::info!("WebSocket server listening on {}", listener.local_addr()?);
 
         loop {
             match listener.accept().await {
                 Ok((stream, addr)) => {
                     log::info!("New WebSocket connection from {}", addr);
 
-                    let sender = self.sender.clone();
+                    let queue = self.queue.clone();
                     let receiver = self.sender.subscribe();
-                    let client_count = self.client_count.clone();
+                    let overlays = self.overlays.clone();
+                    let authorize = authorize.clone();
 
                     tokio::spawn(async move {
-                        if let Err(e) =
-                            handle_connection(stream, sender, receiver, client_count).await
-                        {
+                        // The callback signature is dictated by tungstenite
+                        #[allow(clippy::result_large_err)]
+                        let check = |request: &Request, response: Response| {
+                            if authorize(request.headers(), request.uri().query()) {
+                                Ok(response)
+                            } else {
+                                log::warn!(
+                                    "Rejected WebSocket connection from another site or without a valid token"
+                                );
+                                let mut error =
+                                    ErrorResponse::new(Some("Unauthorized".to_string()));
+                                *error.status_mut() = StatusCode::UNAUTHORIZED;
+                                Err(error)
+                            }
+                        };
+
+                        let result = match accept_hdr_async(stream, check).await {
+                            Ok(ws_stream) => {
+                                handle_connection(ws_stream, queue, receiver, overlays).await
+                            }
+                            Err(e) => Err(e.into()),
+                        };
+                        if let Err(e) = result {
                             log::error!("Error handling WebSocket connection from {}: {}", addr, e);
                         }
                     });
@@ -116,6 +397,22 @@ impl WebSocketServer {
         }
     }
 
+    /// Serves a connection that was upgraded by the HTTP server on `/ws`.
+    pub fn serve_upgraded<S>(&self, ws_stream: WebSocketStream<S>)
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let queue = self.queue.clone();
+        let receiver = self.sender.subscribe();
+        let overlays = self.overlays.clone();
+
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(ws_stream, queue, receiver, overlays).await {
+                log::error!("Error handling upgraded WebSocket connection: {}", e);
+            }
+        });
+    }
+
     pub async fn _broadcast(
         &self,
         message: WebSocketMessage,
@@ -126,31 +423,91 @@ impl WebSocketServer {
     }
 }
 
-async fn handle_connection(
-    stream: TcpStream,
-    _sender: broadcast::Sender<WebSocketMessage>,
+async fn handle_connection<S>(
+    ws_stream: WebSocketStream<S>,
+    queue: ToastQueue,
     mut receiver: broadcast::Receiver<WebSocketMessage>,
-    client_count: Arc<Mutex<usize>>,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let ws_stream = accept_async(stream).await?;
+    overlays: Arc<OverlayRegistry>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
     log::info!("WebSocket connection established");
 
-    // Increment client count
-    {
-        let mut count = client_count.lock().await;
-        *count += 1;
-        log::info!("Client connected. Total clients: {}", *count);
-    }
+    let connection = overlays.connect();
+    log::info!(
+        "Client connected. Total clients: {}",
+        overlays.connection_count()
+    );
 
     let (mut ws_sender, mut ws_receiver) = ws_stream.split();
 
-    // Handle incoming messages from client (if any)
-    let client_count_clone = client_count.clone();
-    let receive_task = tokio::spawn(async move {
+    // Greet the client; it has to answer with its own hello before receiving anything else
+    ws_sender
+        .send(WebSocketMessage::hello().to_message()?)
+        .await?;
+    let (handshake_tx, handshake_rx) = oneshot::channel::<(u32, ClientRole, Option<String>)>();
+
+    // Handle incoming messages from client
+    let receive_overlays = overlays.clone();
+    let mut receive_task = tokio::spawn(async move {
+        let overlays = receive_overlays;
+        let mut handshake_tx = Some(handshake_tx);
+
         while let Some(msg) = ws_receiver.next().await {
             match msg {
                 Ok(Message::Text(text)) => {
                     log::debug!("Received message from client: {}", text);
+
+                    match serde_json::from_str::<ClientMessage>(&text) {
+                        Ok(ClientMessage::Hello {
+                            protocol_version,
+                            role,
+                            name,
+                            viewport,
+                            channel,
+                        }) => {
+                            let channel = channel.filter(|c| !c.is_empty());
+                            let Some(tx) = handshake_tx.take() else {
+                                continue;
+                            };
+                            if role == ClientRole::Overlay && protocol_version == PROTOCOL_VERSION {
+                                overlays.register(
+                                    connection,
+                                    OverlayInfo {
+                                        name: name.unwrap_or_else(|| format!("#{}", connection)),
+                                        width: viewport.map(|v| v.width),
+                                        height: viewport.map(|v| v.height),
+                                        channel: channel.clone(),
+                                        protocol_version,
+                                        connected_at: chrono::Local::now(),
+                                    },
+                                );
+                            }
+                            let _ = tx.send((protocol_version, role, channel));
+                        }
+                        Ok(ClientMessage::Pong { timestamp }) => {
+                            log::debug!("Received pong for ping {}", timestamp);
+                        }
+                        Ok(ClientMessage::Toast(mut toast)) => match toast.validate() {
+                            Ok(()) => {
+                                toast.sanitize();
+                                queue.push(*toast);
+                            }
+                            Err(e) => log::warn!("Ignoring toast from a client: {}", e),
+                        },
+                        Ok(ClientMessage::Shown { id }) => overlays.toast_shown(connection, &id),
+                        Ok(ClientMessage::Dismissed { id }) => {
+                            overlays.toast_dismissed(connection, &id);
+                            queue.finished(&id);
+                        }
+                        Ok(ClientMessage::AudioFailed { id, reason }) => {
+                            overlays.audio_failed(connection, &id, reason)
+                        }
+                        Err(e) => {
+                            log::debug!("Ignoring unrecognized client message: {}", e);
+                        }
+                    }
                 }
                 Ok(Message::Close(_)) => {
                     log::info!("Client sent close message");
@@ -163,22 +520,70 @@ async fn handle_connection(
                 _ => {}
             }
         }
-
-        // Decrement client count when connection closes
-        let mut count = client_count_clone.lock().await;
-        *count -= 1;
-        log::info!("Client disconnected. Total clients: {}", *count);
     });
 
     // Handle outgoing messages to client
-    let send_task = tokio::spawn(async move {
-        while let Ok(message) = receiver.recv().await {
+    let send_overlays = overlays.clone();
+    let mut send_task = tokio::spawn(async move {
+        let overlays = send_overlays;
+        let handshake = tokio::time::timeout(HANDSHAKE_TIMEOUT, handshake_rx).await;
+        let (client_version, channel) = match handshake {
+            Ok(Ok((_, ClientRole::Controller, _))) => {
+                // Controllers only push messages, they never display anything
+                std::future::pending::<()>().await;
+                (None, None)
+            }
+            Ok(Ok((version, ClientRole::Overlay, channel))) => (Some(version), channel),
+            _ => (None, None),
+        };
+
+        if client_version != Some(PROTOCOL_VERSION) {
+            log::warn!(
+                "Incompatible overlay (protocol {:?}, expected {}), sending refresh notice",
+                client_version,
+                PROTOCOL_VERSION
+            );
+
+            // Keep the connection open so the overlay does not reconnect and repeat the notice
+            if let Ok(notice) = WebSocketMessage::outdated_overlay_notice().to_message() {
+                let _ = ws_sender.send(notice).await;
+            }
+            std::future::pending::<()>().await;
+        }
+
+        let mut ping = tokio::time::interval(PING_INTERVAL);
+        ping.tick().await;
+
+        loop {
+            let message = tokio::select! {
+                received = receiver.recv() => match received {
+                    Ok(message) => message,
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        log::warn!("Client lagged behind, {} messages skipped", skipped);
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+                _ = ping.tick() => WebSocketMessage::Ping {
+                    timestamp: chrono::Local::now().timestamp_millis(),
+                },
+            };
+
+            if let WebSocketMessage::Toast(toast) = &message
+                && !toast.targets(channel.as_deref())
+            {
+                continue;
+            }
+
             match message.to_message() {
                 Ok(ws_message) => {
                     if let Err(e) = ws_sender.send(ws_message).await {
                         log::error!("Failed to send message to client: {}", e);
                         break;
                     }
+                    if let WebSocketMessage::Toast(toast) = &message {
+                        overlays.toast_sent(connection, toast);
+                    }
                 }
                 Err(e) => {
                     log::error!("Failed to serialize message: {}", e);
@@ -187,15 +592,141 @@ async fn handle_connection(
         }
     });
 
-    // Wait for either task to complete
+    // Wait for either task to complete, then stop the other one
     tokio::select! {
-        _ = receive_task => {
+        _ = &mut receive_task => {
             log::debug!("Receive task completed");
+            send_task.abort();
         }
-        _ = send_task => {
+        _ = &mut send_task => {
             log::debug!("Send task completed");
+            receive_task.abort();
         }
     }
 
+    overlays.disconnect(connection);
+    log::info!(
+        "Client disconnected. Total clients: {}",
+        overlays.connection_count()
+    );
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn toast() -> ToastMessage {
+        let mut config = ToasterConfig {
+            enable_sound: true,
+            sound_file_id: Some(Uuid::new_v4().to_string()),
+            ..Default::default()
+        };
+        config.channels.push("bottom-left".to_string());
+        ToastMessage::from_config(&config, "Title".to_string(), "Content".to_string())
+    }
+
+    #[test]
+    fn toasts_from_the_config_are_valid() {
+        assert_eq!(toast().validate(), Ok(()));
+    }
+
+    #[test]
+    fn client_fields_are_checked() {
+        let tampered: [fn(&mut ToastMessage); 7] = [
+            |t| t.color_1 = "red; background: url(https://example.com)".to_string(),
+            |t| t.text_color = "#FFF\" onload=\"x".to_string(),
+            |t| t.sound_url = Some("https://example.com/sound.mp3".to_string()),
+            |t| t.sound_url = Some("/audio/../config.toml".to_string()),
+            |t| t.duration = 3_600_000,
+            |t| t.direction = "diagonal".to_string(),
+            |t| t.channels = vec!["a b".to_string()],
+        ];
+        for tamper in tampered {
+            let mut toast = toast();
+            tamper(&mut toast);
+            assert!(toast.validate().is_err(), "{:?}", toast);
+        }
+    }
+
+    type Client = WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
+    /// Connects and says hello as an overlay on `channel`.
+    async fn overlay(addr: std::net::SocketAddr, channel: Option<&str>) -> Client {
+        let (mut client, _) = tokio_tungstenite::connect_async(format!("ws://{}", addr))
+            .await
+            .unwrap();
+        client.next().await.unwrap().unwrap(); // the server's hello
+        let hello = ClientMessage::Hello {
+            protocol_version: PROTOCOL_VERSION,
+            role: ClientRole::Overlay,
+            name: channel.map(str::to_string),
+            viewport: None,
+            channel: channel.map(str::to_string),
+        };
+        client.send(hello.to_message().unwrap()).await.unwrap();
+        client
+    }
+
+    /// Ids of the toasts received until the one with `last_id`.
+    async fn toasts_until(client: &mut Client, last_id: &str) -> Vec<String> {
+        let mut ids = Vec::new();
+        while let Some(Ok(Message::Text(text))) = client.next().await {
+            if let Ok(WebSocketMessage::Toast(toast)) = serde_json::from_str(&text) {
+                ids.push(toast.id.clone());
+                if toast.id == last_id {
+                    break;
+                }
+            }
+        }
+        ids
+    }
+
+    #[tokio::test]
+    async fn toasts_reach_the_overlays_of_their_channels() {
+        let server = Arc::new(WebSocketServer::new(QueueSettings {
+            policy: crate::config::OverlapPolicy::Stack,
+            max_visible: 5,
+        }));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let running = server.clone();
+        tokio::spawn(async move { running.start(listener, |_, _| true).await });
+
+        let mut main = overlay(addr, Some("main")).await;
+        let mut chat = overlay(addr, Some("chat")).await;
+        let mut everywhere = overlay(addr, None).await;
+        // Toasts are only sent after the hello was handled
+        while server.overlays().overlays().len() < 3 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let mut scoped = toast();
+        scoped.channels = vec!["main".to_string()];
+        let unscoped = ToastMessage {
+            channels: Vec::new(),
+            ..toast()
+        };
+        let sender = server.get_sender();
+        sender
+            .send(WebSocketMessage::Toast(Box::new(scoped.clone())))
+            .unwrap();
+        sender
+            .send(WebSocketMessage::Toast(Box::new(unscoped.clone())))
+            .unwrap();
+
+        let received = tokio::time::timeout(Duration::from_secs(5), async {
+            [
+                toasts_until(&mut main, &unscoped.id).await,
+                toasts_until(&mut chat, &unscoped.id).await,
+                toasts_until(&mut everywhere, &unscoped.id).await,
+            ]
+        })
+        .await
+        .unwrap();
+        assert_eq!(received[0], [scoped.id.as_str(), unscoped.id.as_str()]);
+        assert_eq!(received[1], [unscoped.id.as_str()]);
+        assert_eq!(received[2], [unscoped.id.as_str()]);
+    }
+}